@@ -0,0 +1,28 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use huffman::encode::{compress_chars, compress_to_writer};
+use huffman::Config;
+
+fn compress(c: &mut Criterion) {
+    let text = include_str!("../bird").repeat(256);
+    let chars: Vec<char> = text.chars().collect();
+    let config = Config::default();
+
+    c.bench_function("compress_to_writer (&str)", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            compress_to_writer(black_box(text.as_bytes()), &mut out).unwrap();
+            out
+        })
+    });
+
+    c.bench_function("compress_chars (&[char])", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            compress_chars(&config, black_box(&chars), &mut out).unwrap();
+            out
+        })
+    });
+}
+
+criterion_group!(benches, compress);
+criterion_main!(benches);
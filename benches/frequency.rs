@@ -0,0 +1,17 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use huffman::encode::{byte_frequency_map, byte_frequency_table};
+
+fn count(c: &mut Criterion) {
+    let bytes = include_bytes!("../bird").repeat(1024);
+
+    c.bench_function("byte_frequency_table (array)", |b| {
+        b.iter(|| byte_frequency_table(black_box(&bytes)))
+    });
+
+    c.bench_function("byte_frequency_map (HashMap)", |b| {
+        b.iter(|| byte_frequency_map(black_box(&bytes)))
+    });
+}
+
+criterion_group!(benches, count);
+criterion_main!(benches);
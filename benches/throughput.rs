@@ -0,0 +1,59 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use huffman::decode::decode_bytes;
+use huffman::encode::{encode_bytes, generate_encoding_table};
+
+const SIZES: [usize; 3] = [4 << 10, 64 << 10, 1 << 20];
+
+/// Text of `len` bytes from a fixed seed, `skewed` halves the weight of each
+/// next symbol (a deep, lopsided tree), otherwise every symbol is about as
+/// likely (a shallow, balanced tree).
+fn corpus(len: usize, skewed: bool) -> String {
+    let symbols: Vec<char> = ('a'..='z').chain('A'..='Z').chain('0'..='9').chain([' ', '\n']).collect();
+
+    let mut seed: u32 = 0x7e57_da7a;
+    let mut text = String::with_capacity(len);
+
+    while text.len() < len {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let random = seed >> 8;
+
+        let index = match skewed {
+            true => (random.trailing_ones() as usize).min(symbols.len() - 1),
+            false => random as usize % symbols.len(),
+        };
+
+        text.push(symbols[index]);
+    }
+
+    text
+}
+
+fn throughput(c: &mut Criterion) {
+    for (name, skewed) in [("skewed", true), ("uniform", false)] {
+        let mut group = c.benchmark_group(name);
+
+        for size in SIZES {
+            let text = corpus(size, skewed);
+            let compressed = encode_bytes(text.as_bytes());
+
+            group.throughput(Throughput::Bytes(size as u64));
+
+            group.bench_with_input(BenchmarkId::new("generate_encoding_table", size), &text, |b, text| {
+                b.iter(|| generate_encoding_table(black_box(text)).unwrap())
+            });
+
+            group.bench_with_input(BenchmarkId::new("encode_bytes", size), &text, |b, text| {
+                b.iter(|| encode_bytes(black_box(text.as_bytes())))
+            });
+
+            group.bench_with_input(BenchmarkId::new("decode_bytes", size), &compressed, |b, compressed| {
+                b.iter(|| decode_bytes(black_box(compressed)).unwrap())
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use bitvec::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use huffman::decode::{tread, tread_walk, Root};
+
+fn bird_tree() -> (Root, BitVec<u8, Lsb0>) {
+    let contents = include_str!("../bird").repeat(16);
+
+    let table = huffman::encode::generate_encoding_table(&contents).unwrap();
+
+    let header: HashMap<char, String> = table
+        .iter()
+        .map(|(symbol, bits)| {
+            let code = bits.iter().by_vals().map(|bit| if bit { '1' } else { '0' }).collect();
+            (*symbol, code)
+        })
+        .collect();
+
+    let mut payload: BitVec<u8, Lsb0> = BitVec::new();
+    for symbol in contents.chars() {
+        payload.extend(&table[&symbol]);
+    }
+
+    (Root::from_table(&header), payload)
+}
+
+fn decode(c: &mut Criterion) {
+    let (tree, payload) = bird_tree();
+
+    c.bench_function("tread (lookup table)", |b| {
+        b.iter(|| tread(black_box(&tree), payload.clone()).unwrap())
+    });
+
+    c.bench_function("tread_walk (tree walk)", |b| {
+        b.iter(|| tread_walk(black_box(&tree), payload.clone()).unwrap())
+    });
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,257 @@
+//! Several input files compressed into one archive.
+//!
+//! An archive starts with `HUFA` and the format version byte, then `<count>:`
+//! and, per member, `<len>:<name>`, the 8 byte little endian length of the member
+//! and the member itself: a complete compressed file as `compress_with` writes it.
+//!
+//! Every member carries its own table, built from that file alone, and its own
+//! checksum. Files with different alphabets (text next to binary) keep codes
+//! fitted to them, and a damaged member is reported by name.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::decode::{decompress_with, read_len_prefix};
+use crate::encode::{self, CompressionStats};
+use crate::{Config, TempOutput, FORMAT_VERSION};
+
+/// Bytes every archive starts with, followed by the format version byte.
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"HUFA";
+
+/// Compresses every file of `inputs` into one archive written to `out`,
+/// members are named after the file names of the inputs.
+pub fn compress_archive<W: Write>(config: &Config, inputs: &[String], out: &mut W) -> Result<CompressionStats, Box<dyn Error>> {
+    let mut names = HashSet::new();
+    let (mut original_bytes, mut distinct_symbols) = (0, 0);
+
+    let mut archive = Vec::new();
+    archive.extend_from_slice(ARCHIVE_MAGIC);
+    archive.push(FORMAT_VERSION);
+    archive.extend_from_slice(format!("{}:", inputs.len()).as_bytes());
+
+    for input in inputs {
+        let name = member_name(Path::new(input))?;
+
+        if !names.insert(name.clone()) {
+            return Err(format!("two inputs are named `{}`, archive members need distinct names", name).into());
+        }
+
+        let contents = fs::read(input)?;
+
+        let config = Config {
+            input_file: input.clone(),
+            symbols: Some(encode::input_symbol_mode(config, &contents)),
+            ..config.clone()
+        };
+
+        let mut member = Vec::new();
+        encode::compress_with(&config, &contents, &mut member)?;
+
+        archive.extend_from_slice(format!("{}:{}", name.len(), name).as_bytes());
+        archive.extend_from_slice(&(member.len() as u64).to_le_bytes());
+        archive.extend_from_slice(&member);
+
+        original_bytes += contents.len() as u64;
+        distinct_symbols = distinct_symbols.max(encode::distinct_symbols(&config, &contents)?);
+    }
+
+    out.write_all(&archive)?;
+
+    Ok(CompressionStats::new(original_bytes, archive.len() as u64, distinct_symbols))
+}
+
+/// Decompresses every member of `archive` into a file of its name in `dir`,
+/// returning the paths written. Nothing is written unless every member decodes.
+pub fn extract_archive(config: &Config, archive: &[u8], dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut reader = archive
+        .strip_prefix(ARCHIVE_MAGIC.as_slice())
+        .ok_or_else(|| invalid("not an archive"))?;
+
+    let mut version = [0u8];
+    reader.read_exact(&mut version)?;
+
+    if version[0] != FORMAT_VERSION {
+        return Err(crate::HuffmanError::UnsupportedVersion(version[0]).into());
+    }
+
+    let count = read_len_prefix(&mut reader)?;
+
+    let mut names = HashSet::new();
+    let mut members = Vec::new();
+
+    for _ in 0..count {
+        let name_len = read_len_prefix(&mut reader)?;
+
+        // the length comes from the archive, it can't be trusted with an allocation
+        if name_len > reader.len() {
+            return Err(invalid("member name is truncated"));
+        }
+
+        let mut name = vec![0u8; name_len];
+        reader.read_exact(&mut name)?;
+
+        let name = String::from_utf8(name).map_err(|_| invalid("member name is not UTF-8"))?;
+
+        // names come from the archive, they must not reach outside of `dir`
+        if member_name(Path::new(&name)).ok().as_ref() != Some(&name) {
+            return Err(invalid(&format!("invalid member name `{}`", name)));
+        }
+
+        // a second member of the same name would overwrite the first
+        if !names.insert(name.clone()) {
+            return Err(invalid(&format!("two members are named `{}`", name)));
+        }
+
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+
+        let len = usize::try_from(u64::from_le_bytes(len))
+            .ok()
+            .filter(|&len| len <= reader.len())
+            .ok_or_else(|| invalid(&format!("member `{}` is truncated", name)))?;
+
+        let (member, rest) = reader.split_at(len);
+        reader = rest;
+
+        let mut decoded = Vec::new();
+        decompress_with(config, &mut &member[..], &mut decoded)
+            .map_err(|err| io::Error::new(err.kind(), format!("member `{}`: {}", name, err)))?;
+
+        members.push((name, decoded));
+    }
+
+    // every target is checked before any member is written
+    if let Some(path) = members.iter().map(|(name, _)| dir.join(name)).find(|path| !config.force && path.exists()) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("`{}` already exists, use '--force' to overwrite it", path.display())
+        ));
+    }
+
+    let mut written = Vec::new();
+
+    for (name, decoded) in members {
+        let path = dir.join(&name);
+
+        TempOutput::write(dir.join(format!(".{}.huffman-tmp", name)), &decoded, config.write_chunk_size)?
+            .rename_to(&path)?;
+
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// The file name of `path`, which is all of a member name.
+fn member_name(path: &Path) -> Result<String, io::Error> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or_else(|| invalid(&format!("`{}` has no file name to archive it under", path.display())))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn archive_round_trip() {
+        let dir = std::env::temp_dir().join("huffman_archive");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("out")).unwrap();
+
+        let first = "the first member of the archive\n".repeat(20);
+        let second = [0u8, 1, 2, 255, 254, 0, 0, 7];
+
+        fs::write(dir.join("a.txt"), &first).unwrap();
+        fs::write(dir.join("b.bin"), second).unwrap();
+
+        let inputs = ["a.txt", "b.bin"].map(|name| dir.join(name).to_string_lossy().into_owned());
+
+        let mut archive = Vec::new();
+        let stats = compress_archive(&Config::default(), &inputs, &mut archive).unwrap();
+
+        assert!(archive.starts_with(ARCHIVE_MAGIC));
+        assert_eq!(stats.original_bytes, (first.len() + second.len()) as u64);
+        assert_eq!(stats.compressed_bytes, archive.len() as u64);
+
+        let written = extract_archive(&Config::default(), &archive, &dir.join("out")).unwrap();
+
+        assert_eq!(written, [dir.join("out/a.txt"), dir.join("out/b.bin")]);
+        assert_eq!(fs::read(dir.join("out/a.txt")).unwrap(), first.as_bytes());
+        assert_eq!(fs::read(dir.join("out/b.bin")).unwrap(), second);
+
+        // existing files need `--force`
+        assert!(extract_archive(&Config::default(), &archive, &dir.join("out")).is_err());
+        extract_archive(&Config { force: true, ..Config::default() }, &archive, &dir.join("out")).unwrap();
+
+        // one existing file stops the extraction before anything is written
+        fs::remove_file(dir.join("out/a.txt")).unwrap();
+        assert!(extract_archive(&Config::default(), &archive, &dir.join("out")).is_err());
+        assert!(!dir.join("out/a.txt").exists());
+
+        // a damaged archive writes nothing
+        let truncated = &archive[..archive.len() - 1];
+        assert!(extract_archive(&Config::default(), truncated, &dir).is_err());
+        assert!(!dir.join("a.txt.o").exists() && fs::read_dir(&dir).unwrap().count() == 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn member_names_stay_inside_the_directory() {
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.push(FORMAT_VERSION);
+        archive.extend_from_slice(b"1:5:../ab");
+
+        let err = extract_archive(&Config::default(), &archive, Path::new(".")).unwrap_err();
+        assert!(err.to_string().contains("invalid member name"), "{}", err);
+    }
+
+    #[test]
+    fn duplicate_member_names_are_an_error() {
+        let dir = std::env::temp_dir().join("huffman_archive_duplicates");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let input = dir.join("twice.txt");
+        fs::write(&input, "the same name twice\n").unwrap();
+
+        let mut single = Vec::new();
+        compress_archive(&Config::default(), &[input.to_string_lossy().into_owned()], &mut single).unwrap();
+
+        // the one member after `<count>:`, repeated
+        let header_len = ARCHIVE_MAGIC.len() + 1 + b"1:".len();
+        let member = &single[header_len..];
+
+        let mut archive = single[..header_len - 2].to_vec();
+        archive.extend_from_slice(b"2:");
+        archive.extend_from_slice(member);
+        archive.extend_from_slice(member);
+
+        fs::remove_file(&input).unwrap();
+
+        let err = extract_archive(&Config::default(), &archive, &dir).unwrap_err();
+        assert!(err.to_string().contains("two members are named `twice.txt`"), "{}", err);
+        assert!(!input.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn oversized_name_length_is_an_error() {
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.push(FORMAT_VERSION);
+        archive.extend_from_slice(b"1:99999999999999999:x");
+
+        let err = extract_archive(&Config::default(), &archive, Path::new(".")).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+    }
+}
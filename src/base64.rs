@@ -0,0 +1,283 @@
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+
+use crate::{decode, encode, Config};
+
+/// Standard base64 alphabet (RFC 4648), padded with `=`.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Bytes taken from the inner reader for each refill of a `Base64Reader`.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Compresses `reader` in two passes straight into base64 text on `out`,
+/// without buffering the compressed bytes.
+pub fn compress_base64<R: Read + Seek, W: Write>(config: &Config, reader: R, out: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut writer = Base64Writer::new(out);
+
+    encode::compress_seekable(config, reader, &mut writer)?;
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Decompresses base64 text of a compressed file, decoding the base64 while it's read.
+/// Whitespace (such as line breaks) in the text is ignored.
+pub fn decompress_base64<R: Read, W: Write>(reader: R, out: &mut W) -> Result<(), io::Error> {
+    decode::decompress_from_reader(&mut BufReader::new(Base64Reader::new(reader)), out)
+}
+
+/// Writes everything written to it as base64 text to the inner writer.
+/// The last one or two bytes are only written, padded, by `finish`.
+pub struct Base64Writer<W: Write> {
+    inner: W,
+    /// Bytes of an incomplete group of three.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Base64Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, pending: Vec::with_capacity(3) }
+    }
+
+    /// Writes the padded last group and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            let mut text = encode_group(&self.pending);
+
+            // 1 byte is 2 characters, 2 bytes are 3
+            text[self.pending.len() + 1..].fill(b'=');
+
+            self.inner.write_all(&text)?;
+        }
+
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Base64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = buf;
+
+        // complete the group left over from the previous write
+        if !self.pending.is_empty() {
+            let missing = (3 - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..missing]);
+            bytes = &bytes[missing..];
+
+            if self.pending.len() < 3 {
+                return Ok(buf.len());
+            }
+
+            self.inner.write_all(&encode_group(&self.pending))?;
+            self.pending.clear();
+        }
+
+        let groups = bytes.chunks_exact(3);
+        self.pending.extend_from_slice(groups.remainder());
+
+        let text: Vec<u8> = groups.flat_map(encode_group).collect();
+        self.inner.write_all(&text)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Base64 characters of up to three bytes, as if zero padded to three.
+fn encode_group(bytes: &[u8]) -> [u8; 4] {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0) as u32;
+    let group = byte(0) << 16 | byte(1) << 8 | byte(2);
+
+    [18, 12, 6, 0].map(|shift| ALPHABET[(group >> shift & 0x3f) as usize])
+}
+
+/// Reads base64 text from the inner reader and yields the bytes it encodes.
+pub struct Base64Reader<R: Read> {
+    inner: BufReader<R>,
+    /// Characters of an incomplete group of four.
+    quad: Vec<u8>,
+    /// Decoded bytes not yet handed out, from `position` on.
+    decoded: Vec<u8>,
+    position: usize,
+    /// A padded group ended the text.
+    padded: bool,
+}
+
+impl<R: Read> Base64Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: BufReader::with_capacity(READ_CHUNK, inner),
+            quad: Vec::with_capacity(4),
+            decoded: Vec::new(),
+            position: 0,
+            padded: false,
+        }
+    }
+
+    /// Decodes the next chunk of text, leaving `decoded` empty at the end of the text.
+    fn refill(&mut self) -> io::Result<()> {
+        self.decoded.clear();
+        self.position = 0;
+
+        while self.decoded.is_empty() {
+            let text = self.inner.fill_buf()?;
+
+            if text.is_empty() {
+                return match self.quad.len() {
+                    0 => Ok(()),
+                    _ => Err(invalid("base64 text ends inside a group")),
+                };
+            }
+
+            for &c in text {
+                if c.is_ascii_whitespace() {
+                    continue;
+                }
+
+                if self.padded {
+                    return Err(invalid("base64 text continues after its padding"));
+                }
+
+                self.quad.push(c);
+
+                if self.quad.len() == 4 {
+                    self.padded = decode_quad(&self.quad, &mut self.decoded)?;
+                    self.quad.clear();
+                }
+            }
+
+            let consumed = text.len();
+            self.inner.consume(consumed);
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Base64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.decoded.len() {
+            self.refill()?;
+        }
+
+        let available = &self.decoded[self.position..];
+        let n = available.len().min(buf.len());
+
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+/// Appends the bytes of four base64 characters to `out`,
+/// returning whether the group was padded (and so has to be the last one).
+fn decode_quad(quad: &[u8], out: &mut Vec<u8>) -> io::Result<bool> {
+    let padding = quad.iter().rev().take_while(|&&c| c == b'=').count();
+
+    if padding > 2 {
+        return Err(invalid("base64 group with more than two padding characters"));
+    }
+
+    let mut group = 0u32;
+
+    for &c in &quad[..4 - padding] {
+        let value = ALPHABET
+            .iter()
+            .position(|&symbol| symbol == c)
+            .ok_or_else(|| invalid("invalid base64 character"))?;
+
+        group = group << 6 | value as u32;
+    }
+
+    group <<= 6 * padding;
+
+    out.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+
+    Ok(padding > 0)
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn to_base64(bytes: &[u8]) -> String {
+        let mut writer = Base64Writer::new(Vec::new());
+
+        // odd sized writes split the groups
+        for chunk in bytes.chunks(5) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn from_base64(text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        Base64Reader::new(text.as_bytes()).read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    #[test]
+    fn rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (bytes, text) in vectors {
+            assert_eq!(to_base64(bytes.as_bytes()), text);
+            assert_eq!(from_base64(text).unwrap(), bytes.as_bytes());
+        }
+
+        assert_eq!(from_base64("Zm9v\nYmFy\n").unwrap(), b"foobar");
+
+        assert!(from_base64("Zm9").is_err());
+        assert!(from_base64("Zm$v").is_err());
+        assert!(from_base64("Zg==Zm9v").is_err());
+    }
+
+    #[test]
+    fn large_round_trip_through_base64() {
+        let words = ["base64", "streams", "ünïcödé", "語", "without", "buffers", "\n"];
+        let mut seed: u32 = 0xba5e_0064;
+        let contents: String = (0..60_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                words[(seed >> 16) as usize % words.len()].to_string() + " "
+            })
+            .collect();
+
+        let mut text = Vec::new();
+        compress_base64(&Config::default(), Cursor::new(contents.as_bytes()), &mut text).unwrap();
+
+        assert!(text.iter().all(|c| ALPHABET.contains(c) || *c == b'='));
+
+        let mut compressed = Vec::new();
+        encode::compress_seekable(&Config::default(), Cursor::new(contents.as_bytes()), &mut compressed).unwrap();
+
+        assert_eq!(from_base64(std::str::from_utf8(&text).unwrap()).unwrap(), compressed);
+
+        let mut decoded = Vec::new();
+        decompress_base64(&text[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+}
@@ -0,0 +1,97 @@
+//! Conversions between the input's text encoding and the UTF-8 text the
+//! huffman model works on. Encodings other than UTF-8 need the `encoding_rs` feature.
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind};
+
+/// Decodes `bytes` in the encoding named by `label` (UTF-8 when `None`).
+pub fn decode<'a>(bytes: &'a [u8], label: Option<&str>) -> Result<Cow<'a, str>, Error> {
+    match label {
+        None => std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")),
+        Some(label) => decode_with(bytes, label),
+    }
+}
+
+/// Encodes `text` into the encoding named by `label` (UTF-8 when `None`).
+pub fn encode<'a>(text: &'a str, label: Option<&str>) -> Result<Cow<'a, [u8]>, Error> {
+    match label {
+        None => Ok(Cow::Borrowed(text.as_bytes())),
+        Some(label) => encode_with(text, label),
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+fn lookup(label: &str) -> Result<&'static encoding_rs::Encoding, Error> {
+    encoding_rs::Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, format!("unknown text encoding `{}`", label))
+    })
+}
+
+#[cfg(feature = "encoding_rs")]
+fn decode_with<'a>(bytes: &'a [u8], label: &str) -> Result<Cow<'a, str>, Error> {
+    let encoding = lookup(label)?;
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("input is not valid {}", encoding.name()))
+        })
+}
+
+#[cfg(feature = "encoding_rs")]
+fn encode_with<'a>(text: &'a str, label: &str) -> Result<Cow<'a, [u8]>, Error> {
+    let encoding = lookup(label)?;
+
+    let (bytes, _, unmappable) = encoding.encode(text);
+
+    if unmappable {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("decoded text can't be represented in {}", encoding.name())
+        ));
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "encoding_rs"))]
+fn decode_with<'a>(_bytes: &'a [u8], label: &str) -> Result<Cow<'a, str>, Error> {
+    Err(unsupported(label))
+}
+
+#[cfg(not(feature = "encoding_rs"))]
+fn encode_with<'a>(_text: &'a str, label: &str) -> Result<Cow<'a, [u8]>, Error> {
+    Err(unsupported(label))
+}
+
+#[cfg(not(feature = "encoding_rs"))]
+fn unsupported(label: &str) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!("text encoding `{}` needs the `encoding_rs` feature", label)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn utf8_is_the_default() {
+        assert_eq!(decode("héllo".as_bytes(), None).unwrap(), "héllo");
+        assert_eq!(encode("héllo", None).unwrap(), "héllo".as_bytes());
+        assert!(decode(b"h\xe9llo", None).is_err());
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn latin1_round_trip() {
+        let bytes = b"caf\xe9 na\xefve \xff";
+
+        let text = decode(bytes, Some("latin1")).unwrap();
+
+        assert_eq!(text, "café naïve ÿ");
+        assert_eq!(encode(&text, Some("latin1")).unwrap(), &bytes[..]);
+    }
+}
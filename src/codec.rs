@@ -0,0 +1,115 @@
+//! A byte model built once and reused on many messages, without the
+//! compressed file format around them: no header, no footer, just the code bits.
+
+use std::collections::HashMap;
+
+use bitvec::prelude::*;
+
+use crate::decode::{tread, Root};
+use crate::encode::{byte_encoding_table, UnmappedSymbol};
+use crate::{Config, DecodeError, HuffmanError};
+
+/// Huffman codes of the bytes of a model, with the tree decoding them.
+#[derive(Debug, Clone)]
+pub struct Codec {
+    table: HashMap<u8, BitVec<u8, Msb0>>,
+    huffman_tree: Root,
+}
+
+impl Codec {
+    /// Builds the codes from byte counts, bytes with a zero count get no code.
+    pub fn from_frequencies(frequencies: &[u32; 256]) -> Result<Self, HuffmanError> {
+        let table = byte_encoding_table(&Config::default(), frequencies)?;
+
+        // the decoder keys bytes on the char of the same value, like byte coded files
+        let codes: HashMap<char, String> = table
+            .iter()
+            .map(|(&byte, code)| (char::from(byte), code.iter().by_vals().map(|bit| if bit { '1' } else { '0' }).collect()))
+            .collect();
+
+        Ok(Self {
+            huffman_tree: Root::from_table(&codes),
+            table,
+        })
+    }
+
+    /// Builds the codes from the byte counts of a training corpus.
+    pub fn train(corpus: &[u8]) -> Result<Self, HuffmanError> {
+        let mut frequencies = [0u32; 256];
+        corpus.iter().for_each(|&byte| frequencies[byte as usize] += 1);
+
+        Self::from_frequencies(&frequencies)
+    }
+
+    /// Code of every byte of the model.
+    pub fn table(&self) -> &HashMap<u8, BitVec<u8, Msb0>> {
+        &self.table
+    }
+
+    /// Number of bits `input` encodes to, without encoding it.
+    pub fn encoded_bits(&self, input: &[u8]) -> Result<usize, UnmappedSymbol> {
+        input
+            .iter()
+            .map(|&byte| self.table.get(&byte).map(|code| code.len()).ok_or(UnmappedSymbol(char::from(byte))))
+            .sum()
+    }
+
+    /// The codes of `input` back to back, first code first.
+    /// # Errors
+    /// - `UnmappedSymbol` if `input` has a byte the model has no code for
+    pub fn encode(&self, input: &[u8]) -> Result<BitVec<u8, Lsb0>, UnmappedSymbol> {
+        let mut bits = BitVec::new();
+
+        for &byte in input {
+            let code = self.table.get(&byte).ok_or(UnmappedSymbol(char::from(byte)))?;
+            bits.extend(code.iter().by_vals());
+        }
+
+        Ok(bits)
+    }
+
+    /// Decodes the output of `encode`.
+    /// # Errors
+    /// - `HuffmanError::InvalidCodePath` if `bits` leave the tree, they weren't encoded with this model
+    pub fn decode(&self, bits: &BitVec<u8, Lsb0>) -> Result<Vec<u8>, DecodeError> {
+        let decoded = tread(&self.huffman_tree, bits.clone())?;
+
+        // every symbol of the tree is the char of a byte
+        Ok(decoded.chars().map(|symbol| symbol as u8).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn one_model_codes_many_messages() {
+        let codec = Codec::train(b"the quick brown fox jumps over the lazy dog\n").unwrap();
+
+        let messages: [&[u8]; 5] = [b"fox", b"the dog jumps\n", b"", b"zzz", b"over the lazy brown dog quick"];
+
+        for message in messages {
+            let bits = codec.encode(message).unwrap();
+
+            assert_eq!(bits.len(), codec.encoded_bits(message).unwrap());
+            assert_eq!(codec.decode(&bits).unwrap(), message);
+        }
+
+        // frequent bytes get shorter codes
+        assert!(codec.table()[&b' '].len() < codec.table()[&b'q'].len());
+
+        assert_eq!(codec.encoded_bits(b"fox!"), Err(UnmappedSymbol('!')));
+        assert_eq!(codec.encode(b"fox!"), Err(UnmappedSymbol('!')));
+
+        let mut frequencies = [0u32; 256];
+        frequencies[0x00] = 5;
+        frequencies[0xff] = 1;
+        frequencies[0x80] = 1;
+
+        let codec = Codec::from_frequencies(&frequencies).unwrap();
+        let message = [0xff, 0x00, 0x00, 0x80, 0x00];
+
+        assert_eq!(codec.decode(&codec.encode(&message).unwrap()).unwrap(), message);
+    }
+}
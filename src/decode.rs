@@ -1,380 +1,2807 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{BufReader, BufRead, Error, Write};
-use std::fs::{self, File};
-use crate::Config;
+use std::io::{BufReader, BufRead, Cursor, Error, ErrorKind, Read, Write};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::archive::{extract_archive, ARCHIVE_MAGIC};
+use crate::{charset, transform, Config, OutputTarget, DecodeError, HuffmanError, TempOutput, BLOCKS_TAG, BYTES_TAG, DICTIONARY_TAG, FORMAT_VERSION, MAGIC, MODEL_TAG, STORE_TAG};
+use crate::encode::{Algorithm, BitOrder, CanonicalTable};
+use crate::footer::Footer;
+use crate::remap::Remap;
 use bitvec::prelude::*;
 
 
 pub fn decompress(config: &Config) -> Result<(), Error>{
-    // Open file and create a buffered reader.
-    let file = File::open(config.get_input_file())?;
-
-    let mut reader = BufReader::new(file);
+    decompress_from(config, config.input_source().open()?)
+}
 
-    let reconst = parse_header(&mut reader)?;
+/// `decompress` with the compressed file read from `input` instead of `config.input_file`.
+/// The output goes where `config` says.
+pub fn decompress_from<R: Read>(config: &Config, mut input: R) -> Result<(), Error> {
+    let mut compressed = Vec::new();
+    input.read_to_end(&mut compressed)?;
+
+    if compressed.starts_with(ARCHIVE_MAGIC) {
+        // members are extracted into the `-o` directory, or next to the archive
+        let dir = match &config.output {
+            OutputTarget::File(dir) => dir.clone(),
+            OutputTarget::Derived => Path::new(&config.input_file).parent().map(Path::to_path_buf).unwrap_or_default(),
+            OutputTarget::Stdout => return Err(Error::new(ErrorKind::InvalidInput, "an archive can't be extracted to stdout")),
+        };
 
-    write_decoded(config,&mut reader, reconst)?;
+        let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir };
 
-    Ok(())
-}
+        extract_archive(config, &compressed, &dir)?;
 
-fn write_decoded(config: &Config,reader: &mut BufReader<File>, reconst: Reconst) -> Result<(), Error> {
-    let mut encoded_content: Vec<u8> = Vec::new();
+        return Ok(());
+    }
 
-    reader.read_until(b'\0', &mut encoded_content)?;
+    let mut decoded = Vec::new();
 
-    let bv: BitVec<u8, Lsb0> = BitVec::from_vec(encoded_content);
+    decompress_with(config, &mut &compressed[..], &mut decoded)?;
 
-    let decoded = tread(&reconst.huffman_tree, bv);
+    let inspection = inspect_bytes(&compressed)?;
 
-    let output_path = config.get_output_file()?;
+    // the name stored at compression survives renaming the compressed file
+    let restored;
+    let config = match stored_name_output(config, &inspection.footer.name) {
+        Some(path) => {
+            restored = Config { output: OutputTarget::File(path), ..config.clone() };
+            &restored
+        },
+        None => config,
+    };
 
-    let mut output_file = fs::OpenOptions::new()
-        .write(true)
-        .open(output_path)?;
+    // the output is only created once decoding succeeded.
+    let written = config.write_output(&decoded)?;
 
-    output_file.write_all(decoded.as_bytes())?;
+    if let (Some(written), Some(owner)) = (written, inspection.owner) {
+        restore_owner(&written, owner, config.force)?;
+    }
 
     Ok(())
 }
 
-fn parse_header(reader: &mut BufReader<File>) -> Result<Reconst, Error>{
-    let mut raw_table = String::new();
+/// The output file named after the input name stored in the footer, next to the
+/// compressed file, when `config` leaves the output name to be derived.
+/// Only the last component of the stored name is used, so it can't point
+/// into another directory. `None` without a usable stored name.
+fn stored_name_output(config: &Config, stored: &str) -> Option<PathBuf> {
+    if config.output != OutputTarget::Derived || config.in_place || config.self_extract {
+        return None;
+    }
 
-    let mut line = String::new();
+    let name = stored.rsplit(['/', '\\']).next().filter(|name| !matches!(*name, "" | "." | ".."))?;
 
-    reader.read_line(&mut line)?;
+    Some(config.get_input_file().with_file_name(name))
+}
 
-    // remove last byte 0x0A (\n)
-    line.pop();
-
-    let entry_count: u8 = match line.parse() {
-        Ok(count) => count,
-        Err(err) => {
-            panic!(
-                "Invalid file format! \
-                expected first line to be a number, found: `{}`\
-                \nParseIntError: {}",
-                line,
-                err
-            );
-        },
-    };
+/// Longest code length a header may declare. Counts that fit in a `u32` give
+/// huffman codes of at most 46 bits, longer lengths only come from crafted files.
+const MAX_CODE_LEN: usize = 64;
+
+/// Bytes shown on each side of the first difference by `Mismatch`.
+const MISMATCH_CONTEXT: usize = 16;
+
+/// Bytes per row of the `Mismatch` rendering.
+const MISMATCH_ROW: usize = 8;
+
+/// The first difference found by `verify_against` or `verify_round_trip`, with the bytes around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Offset of the first differing byte.
+    pub offset: u64,
+    /// Offset of the first byte of the context windows.
+    pub start: u64,
+    pub original: Vec<u8>,
+    pub decoded: Vec<u8>,
+}
 
-    for _ in 0..entry_count {
-        let mut buf_line = String::new();
+impl Mismatch {
+    fn new(offset: usize, original: &[u8], decoded: &[u8]) -> Self {
+        // rows start at multiples of the row width
+        let start = offset.saturating_sub(MISMATCH_CONTEXT) / MISMATCH_ROW * MISMATCH_ROW;
+        let end = offset + MISMATCH_CONTEXT;
 
-        reader.read_line(&mut buf_line)?;
+        let window = |bytes: &[u8]| bytes[start.min(bytes.len())..end.min(bytes.len())].to_vec();
 
-        raw_table.push_str(&buf_line);
+        Self {
+            offset: offset as u64,
+            start: start as u64,
+            original: window(original),
+            decoded: window(decoded),
+        }
     }
-
-    Ok(Reconst::from_str(entry_count, &raw_table))
 }
 
-#[derive(Debug)]
-pub struct Reconst {
-    pub encoding_table: HashMap<char, String>,
-    pub huffman_tree: Root
-}
+/// Renders the windows side by side as hex and ASCII,
+/// marking the row with the first difference by `>`.
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let row = |bytes: &[u8], from: usize| {
+            let bytes = bytes.get(from..).unwrap_or_default();
+            let bytes = &bytes[..bytes.len().min(MISMATCH_ROW)];
+
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+
+            format!("{:<w$}  {:<r$}", hex.join(" "), ascii, w = MISMATCH_ROW * 3 - 1, r = MISMATCH_ROW)
+        };
 
-impl Reconst {
-    /// Create header instance from Path 
-    /// # Panics 
-    /// - The generated huffman table doesn't have as many entries as declared in the first line
-    pub fn from_str(entry_count: u8, raw_table: &str) -> Self{
-        let encoding_table = Reconst::huffman_table(&raw_table);
-
-        // length of the generated table should be equal to
-        // the header `entry_count`
-        assert!(encoding_table.len() as u8 == entry_count);
-        
-        let huffman_tree = Root::from_table(&encoding_table);
+        write!(f, "  offset    {:<w$}  decoded", "original", w = MISMATCH_ROW * 4 + 1)?;
 
-        Self {
-            encoding_table,
-            huffman_tree
+        let rows = self.original.len().max(self.decoded.len());
+
+        for from in (0..rows).step_by(MISMATCH_ROW) {
+            let at = self.start + from as u64;
+            let marker = if (at..at + MISMATCH_ROW as u64).contains(&self.offset) { '>' } else { ' ' };
+
+            write!(f, "\n{} {:08x}  {}  {}", marker, at, row(&self.original, from), row(&self.decoded, from))?;
         }
+
+        Ok(())
     }
+}
 
-    pub fn huffman_table(raw: &str) -> HashMap<char, String> {
-        let mut table = HashMap::new();
-        for line in raw.lines() {
-            let key = match line.chars().next() {
-                // Corrects escaped newline character iteraction 
-                // with .lines() iterator
-                Some(k) => if k == '\\' { '\n' } else { k },
-                None => break
-            };
+/// Decompresses `archive` in memory and compares it byte by byte with `original`,
+/// returning the first difference, `None` when they match.
+/// A length difference counts as a difference at the end of the shorter one.
+pub fn verify_against(archive: impl AsRef<Path>, original: impl AsRef<Path>) -> Result<Option<Mismatch>, Error> {
+    let compressed = std::fs::read(archive)?;
+    let original = std::fs::read(original)?;
 
-            let code = if key == '\n' {
-                // Since new lines are escaped by adding an extra escape character..
-                // actual code value start is shifted one index
-                String::from(&line[2..])
-            } else {
-                String::from(&line[1..])
-            };
+    let mut decoded = Vec::new();
+    decompress_from_reader(&mut &compressed[..], &mut decoded)?;
 
-            table.insert(key, code);
-        }
-        table
-    }
+    Ok(first_mismatch(&original, &decoded))
 }
 
-macro_rules! some_boxed_leaf {
-    ($e:expr) => {
-        Some(Box::new(Node::Leaf($e)))
+/// Compresses `original` with `config` and decompresses the result, all in memory,
+/// returning the first difference of the round trip, `None` when it's lossless.
+/// The comparison replaces the checksum check, which would only tell that they differ.
+pub fn verify_round_trip(config: &Config, original: &[u8]) -> Result<Option<Mismatch>, Box<dyn std::error::Error>> {
+    let config = Config {
+        symbols: Some(crate::encode::input_symbol_mode(config, original)),
+        no_verify: true,
+        ..config.clone()
     };
+
+    let mut compressed = Vec::new();
+    crate::encode::compress_with(&config, original, &mut compressed)?;
+
+    let mut decoded = Vec::new();
+    decompress_with(&config, &mut &compressed[..], &mut decoded)?;
+
+    Ok(first_mismatch(original, &decoded))
 }
 
-macro_rules! some_boxed_branch {
-    ($e:expr) => {
-        Some(Box::new(Node::Branch($e)))
-    };
+/// A length difference counts as a difference at the end of the shorter one.
+fn first_mismatch(original: &[u8], decoded: &[u8]) -> Option<Mismatch> {
+    let offset = decoded
+        .iter()
+        .zip(original)
+        .position(|(decoded, original)| decoded != original)
+        .or_else(|| (decoded.len() != original.len()).then(|| decoded.len().min(original.len())));
+
+    offset.map(|offset| Mismatch::new(offset, original, decoded))
 }
 
-macro_rules! extend {
-    ($root:expr, $code:expr, $symbol_value:expr) => {
-        match &$root {
-            Some(tree) => {
-                let sub_root = tree.clone().branch().unwrap_or_else(||
-                    panic!("Failed to extend branch out of sub tree {:?}", tree)
-                );
+/// Gives `path` the stored owner. When the process isn't allowed to (not root)
+/// that is skipped, unless `force` asks for the owner to be restored.
+#[cfg(unix)]
+fn restore_owner(path: &Path, (uid, gid): (u32, u32), force: bool) -> Result<(), Error> {
+    skip_denied(std::os::unix::fs::chown(path, Some(uid), Some(gid)), force)
+}
 
-                $root = some_boxed_branch!(
-                    Root::new_traverse(Some(sub_root), &$code[1..], $symbol_value)
-                );
-            },
-            None => {
-                $root = some_boxed_branch!(
-                    Root::new_traverse(None, &$code[1..], $symbol_value)
-                );
-            }
-        }
-    };
+#[cfg(not(unix))]
+fn restore_owner(_path: &Path, _owner: (u32, u32), _force: bool) -> Result<(), Error> {
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub struct Root {
-    left: Option<Box<Node>>,
-    right: Option<Box<Node>>,
+/// `result` with a permission error turned into success, unless `force` is set.
+#[cfg(unix)]
+fn skip_denied(result: Result<(), Error>, force: bool) -> Result<(), Error> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::PermissionDenied && !force => Ok(()),
+        result => result,
+    }
 }
 
-impl Default for Root {
-    fn default() -> Self {
-        Self {
-            left: None,
-            right: None
+/// Decompresses `compressed_path` into a new file in the system temp directory
+/// and returns its path, removing the file is up to the caller.
+pub fn decompress_to_temp(compressed_path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+    static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let mut reader = BufReader::new(File::open(compressed_path)?);
+
+    let mut decoded = Vec::new();
+
+    decompress_with(&Config::default(), &mut reader, &mut decoded)?;
+
+    // the names are predictable in a shared directory, anything already
+    // there is never opened, the next name is tried instead.
+    loop {
+        let temp = std::env::temp_dir().join(format!(
+            "huffman-{}-{}.tmp",
+            std::process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        match TempOutput::write_new(temp, &decoded, crate::DEFAULT_WRITE_CHUNK_SIZE) {
+            Ok(written) => return Ok(written.into_path()),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
         }
     }
 }
 
-macro_rules! walk {
-    ($sub_tree:expr) => {
-        match $sub_tree{
-            Some(node) => node.clone(),
-            None => panic!("Invalid code or root was provided.")
-        }
-    };
+/// Decompresses everything `reader` yields into `out` with the default settings.
+pub fn decompress_from_reader<R: BufRead, W: Write>(reader: &mut R, out: &mut W) -> Result<(), Error> {
+    decompress_with(&Config::default(), reader, out)
 }
 
-macro_rules! invalid_code {
-    ($invalid_char:expr) => {
-       panic!("Invalid code expected a `0` or `1`, got `{}`", $invalid_char)
-    };
+/// Decompresses a complete compressed file held in memory, such as one from `encode_bytes`.
+pub fn decode_bytes(input: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut decoded = Vec::new();
+
+    decompress_from_reader(&mut &input[..], &mut decoded)?;
+
+    Ok(decoded)
 }
 
-impl Root {
-    pub fn from_table(table: &HashMap<char, String>) -> Self {
-        table.iter().fold(Root::default(), |acc, (key, value)| { 
-            Root::new_traverse(Some(acc), value, *key) 
-        })
+/// Core of `decompress`: decodes the compressed stream in `reader` into `out`
+/// according to `config`.
+pub fn decompress_with<R: BufRead, W: Write>(config: &Config, reader: &mut R, out: &mut W) -> Result<(), Error> {
+    let mut compressed = Vec::new();
+
+    reader.read_to_end(&mut compressed)?;
+
+    let (footer, mut body) = open_container(&compressed)?;
+
+    // the comment and owner are only informational here.
+    read_comment(&mut body)?;
+    read_owner(&mut body)?;
+
+    // stored input is copied through as is.
+    if let Some(stored) = body.strip_prefix(STORE_TAG) {
+        if stored.len() as u64 != footer.original_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("stored {} bytes but the footer declares {}", stored.len(), footer.original_len)
+            ));
+        }
+
+        verify_checksum(config, &footer, stored)?;
+
+        return write_decoded(out, stored, config.write_chunk_size);
     }
 
-    /// Reconstructs the huffman tree through traversal of header code strings
-    /// # Panics
-    /// - On getting an invalid code other than a `0` or `1`
-    /// - On getting an empty string.
-    /// - On overwriting node leaf variant.
-    pub fn new_traverse(bootstrap: Option<Root>, code: &str, symbol_value: char) -> Self {
-        // Use the bootstrap root if it's provided.
-        let mut root = if let Some(_root) = bootstrap { 
-            _root 
-        } else { 
-            Self::default()
-        };
+    if body.starts_with(MODEL_TAG) {
+        return Err(Error::new(ErrorKind::InvalidData, "model files hold no data to decompress"));
+    }
 
-        // consume string slice until reaching the last char
-        // (base condition)
-        if code.len() == 1 {
-            // its safe to unwrap here already explicitly
-            // checked that the str slice has a single character
-            let ch = code.chars().nth(0).unwrap();
+    if let Some(mut coded) = body.strip_prefix(DICTIONARY_TAG) {
+        let decoded = decode_with_dictionary(config, &mut coded, &footer)?;
+        let decoded = charset::encode(&decoded, config.encoding.as_deref())?;
 
-            match ch {
-                '1' => root.right = some_boxed_leaf!(symbol_value),
-                '0' => root.left = some_boxed_leaf!(symbol_value),
-                other_char => invalid_code!(other_char)
-            };
+        verify_checksum(config, &footer, &decoded)?;
 
-            return root;
-        } 
+        return write_decoded(out, &decoded, config.write_chunk_size);
+    }
 
-        match code.chars().next() {
-            Some(ch) => {
-                match ch {
-                    '1' => extend!(root.right, code, symbol_value),
-                    '0' => extend!(root.left, code, symbol_value),
-                    other_char => invalid_code!(other_char) 
-                }
-            },
-            None => panic!("Failed to traverse tree with, got empty code string.")
-        };
+    if let Some(mut blocks) = body.strip_prefix(BLOCKS_TAG) {
+        let decoded = decode_blocks(config, &mut blocks)?;
 
-        root
-    } 
+        verify_checksum(config, &footer, &decoded)?;
 
+        return write_decoded(out, &decoded, config.write_chunk_size);
+    }
 
-    /// Incremental tree traversal a tree given a char (code fragment)
-    /// # Panics
-    /// - On receiving a code that isn't a `0` or `1`
-    /// - On providing an empty code slice.
-    /// - On providing an invalid root provided (tree was reconstructed incorrectly).
-    pub fn walk(root: &Root, code_elem: bool) -> Box<Node> {
-        match code_elem {
-            true => walk!(&root.right),
-            false => walk!(&root.left),
-        }
+    if let Some(mut coded) = body.strip_prefix(BYTES_TAG) {
+        let decoded = decode_byte_section(&mut coded, &footer)?;
+
+        verify_checksum(config, &footer, &decoded)?;
+
+        return write_decoded(out, &decoded, config.write_chunk_size);
+    }
+
+    // the canonical codes follow from their lengths, whichever algorithm built them.
+    read_algorithm(&mut body)?;
+    let order = read_bit_order(&mut body)?;
+
+    let transforms = read_transforms(&mut body)?;
+
+    let symbol_count = read_len_prefix(&mut body)?;
+    let remap = Remap::read_from(&mut body, symbol_count)?;
+
+    let (entry_count, lengths) = read_table(&mut body)?;
+    let encoding_table = canonical_table(&lengths)?;
+    let (payload_len, padding) = read_payload_len(&mut body)?;
+
+    // transforms change the length of the text the payload codes
+    let original_len = transforms.is_empty().then_some(footer.original_len);
+
+    let (reconst, payload) = checked_payload(entry_count, encoding_table, (payload_len, padding), original_len, body)?;
+
+    let payload = match order {
+        BitOrder::Lsb0 => Cow::Borrowed(payload),
+        BitOrder::Msb0 => Cow::Owned(reverse_bit_order(payload)),
+    };
+
+    let mut decoded = decode_payload(&reconst, &payload, padding)?;
+
+    if !remap.is_empty() {
+        decoded = remap.reverse(&decoded)?;
     }
+
+    let decoded = reverse_transforms(decoded, &transforms)?;
+    let decoded = charset::encode(&decoded, config.encoding.as_deref())?;
+
+    verify_checksum(config, &footer, &decoded)?;
+
+    write_decoded(out, &decoded, config.write_chunk_size)
 }
 
-#[derive(Debug, Clone)]
-pub enum Node {
-    Branch(Root),
-    Leaf(char)
+/// Checks the CRC-32 of the decoded `output` against the one in the footer,
+/// unless `config.no_verify` skips it.
+fn verify_checksum(config: &Config, footer: &Footer, output: &[u8]) -> Result<(), Error> {
+    let actual = crc32fast::hash(output);
+
+    match config.no_verify || actual == footer.checksum {
+        true => Ok(()),
+        false => Err(HuffmanError::ChecksumMismatch { expected: footer.checksum, actual }.into()),
+    }
 }
 
-impl Node {
-    pub fn branch(self) -> Option<Root> {
-        match self {
-            Node::Branch(root) => Some(root),
-            Node::Leaf(_) => None,
-        }
+/// Decodes the payload after `DICTIONARY_TAG` with the codes of the `--dictionary`
+/// model file, which has to be the one the file was compressed with.
+fn decode_with_dictionary(config: &Config, body: &mut &[u8], footer: &Footer) -> Result<String, Error> {
+    let dictionary = config.dictionary.as_deref().ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, "this file was compressed with a dictionary, pass it with '--dictionary'")
+    })?;
+
+    let mut id = [0u8; 4];
+    body.read_exact(&mut id)?;
+
+    if u32::from_le_bytes(id) != dictionary_id(dictionary)? {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("`{}` is not the dictionary this file was compressed with", dictionary)
+        ));
     }
 
-    #[cfg(test)]
-    pub fn leaf(self) -> Option<char> {
-        match self {
-            Node::Leaf(symbol) => Some(symbol),
-            Node::Branch(_) => None,
+    let lengths = read_model(dictionary)?;
+    let encoding_table = canonical_table(&lengths)?;
+    let (payload_len, padding) = read_payload_len(body)?;
+
+    let (reconst, payload) = checked_payload(lengths.len(), encoding_table, (payload_len, padding), Some(footer.original_len), body)?;
+
+    decode_payload(&reconst, payload, padding)
+}
+
+/// Names a dictionary by the CRC-32 of its model file.
+pub(crate) fn dictionary_id(path: impl AsRef<Path>) -> Result<u32, Error> {
+    Ok(crc32fast::hash(&std::fs::read(path)?))
+}
+
+/// Decodes the blocks after `BLOCKS_TAG` one after the other, each a compressed file of its own.
+fn decode_blocks(config: &Config, body: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let count = read_len_prefix(body)?;
+
+    let mut decoded = Vec::new();
+
+    for index in 0..count {
+        let mut len = [0u8; 8];
+        body.read_exact(&mut len)?;
+
+        let len = usize::try_from(u64::from_le_bytes(len))
+            .ok()
+            .filter(|&len| len <= body.len())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("block {} is truncated", index)))?;
+
+        let (block, rest) = body.split_at(len);
+        *body = rest;
+
+        // blocks are never split again, nesting them would only recurse without bound
+        let (_, mut block_body) = open_container(block)?;
+        read_comment(&mut block_body)?;
+        read_owner(&mut block_body)?;
+
+        if block_body.starts_with(BLOCKS_TAG) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("block {} is split into blocks itself", index)));
         }
+
+        decompress_with(config, &mut &block[..], &mut decoded)
+            .map_err(|err| Error::new(err.kind(), format!("block {}: {}", index, err)))?;
     }
+
+    Ok(decoded)
 }
 
-/// Incrementally walks the huffman tree using the provided code slice
-/// and returns a decoded string.
-/// # Panics:
-/// - `Root::walk` panic conditions
-pub fn tread(huffman_tree: &Root, code_path: BitVec<u8, Lsb0>) -> String {
-    let mut decoded = String::new();
-    let mut walk_root: Option<Root> = None;
-
-    for code in code_path {
-        let leg = match &walk_root {
-            Some(_leg) => _leg,
-            None => huffman_tree
-        };
+/// Decodes the byte coded section after `BYTES_TAG` back into the input bytes.
+fn decode_byte_section(body: &mut &[u8], footer: &Footer) -> Result<Vec<u8>, Error> {
+    read_algorithm(body)?;
+    let order = read_bit_order(body)?;
 
-        match *Root::walk(leg, code) {
-            Node::Leaf(symbol) => {
-                walk_root = None;
-                decoded.push(symbol);
-            },
-            Node::Branch(root) => {
-                walk_root = Some(root)
-            },
-        };
-    }
+    let (entry_count, lengths) = read_byte_table(body)?;
+    let encoding_table = canonical_table(&lengths)?;
+    let (payload_len, padding) = read_payload_len(body)?;
+
+    let (reconst, payload) = checked_payload(entry_count, encoding_table, (payload_len, padding), Some(footer.original_len), body)?;
+
+    let payload = match order {
+        BitOrder::Lsb0 => Cow::Borrowed(payload),
+        BitOrder::Msb0 => Cow::Owned(reverse_bit_order(payload)),
+    };
 
-    decoded
+    let decoded = decode_payload(&reconst, &payload, padding)?;
+
+    // every symbol is the char of the same value as its byte
+    Ok(decoded.chars().map(|symbol| symbol as u8).collect())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Rebuilds the tree of `encoding_table` and checks it against the declared
+/// `entry_count`, `(payload length, padding)` and `original_len` with `validate_header`,
+/// returning the tree and the payload bytes at the start of `body`.
+fn checked_payload(
+    entry_count: usize,
+    encoding_table: HashMap<char, String>,
+    (payload_len, padding): (u64, u8),
+    original_len: Option<u64>,
+    body: &[u8],
+) -> Result<(Reconst, &[u8]), Error> {
+    let huffman_tree = Root::from_table(&encoding_table);
+
+    let header = Header {
+        entry_count,
+        table_entries: encoding_table.len(),
+        leaf_count: huffman_tree.leaf_count(),
+        max_code_len: encoding_table.values().map(String::len).max().unwrap_or(0),
+        payload_len,
+        padding,
+        original_len,
+    };
 
-    #[test]
-    fn it_parses_huffman_table() {
-        let table_str = String::from("a01\nc001");
-        let table = Reconst::huffman_table(&table_str);
-        assert_eq!(table.get(&'a').unwrap(), "01");
-        assert_eq!(table.get(&'c').unwrap(), "001");
-    }
+    validate_header(&header, body.len())?;
 
-    #[test]
-    fn huffman_table_parser_handles_newlines() {
-        let table_str = String::from("\\n01\n");
+    Ok((Reconst { encoding_table, huffman_tree }, &body[..payload_len as usize]))
+}
 
-        let table = Reconst::huffman_table(&table_str);
+/// Writes the decoded output in writes of at most `chunk_size` bytes.
+/// A reader that went away (`BrokenPipe`) ends the output quietly,
+/// like standard Unix tools, other write errors are returned.
+pub(crate) fn write_decoded<W: Write>(out: &mut W, decoded: &[u8], chunk_size: usize) -> Result<(), Error> {
+    match crate::encode::write_chunked(out, decoded, chunk_size) {
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
 
-        assert_eq!(table.get(&'\n').unwrap(), "01");
+/// Checks the magic and version bytes and reads the footer of a compressed file,
+/// returning the footer and the header and payload bytes in between.
+fn open_container(compressed: &[u8]) -> Result<(Footer, &[u8]), Error> {
+    let version = read_version(&mut &compressed[..])?;
+
+    if version != FORMAT_VERSION {
+        return Err(HuffmanError::UnsupportedVersion(version).into());
     }
 
+    let footer = Footer::read_from(&mut Cursor::new(compressed))?;
 
-    fn basic_tree() -> Root {
-        let table = HashMap::from([
-            ('h', "010".to_string()),
-            ('f', "11".to_string()),
-            ('m', "011".to_string()),
-            ('n', "000".to_string()),
-            ('a', "100".to_string()),
-            ('u', "001".to_string()),
-            ('\n', "101".to_string()),
-        ]);
+    let body = compressed
+        .get(MAGIC.len() + 1..compressed.len() - footer.encoded_len() as usize)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "footer overlaps the format version"))?;
 
-        Root::from_table(&table)
-    }
+    Ok((footer, body))
+}
 
-    #[test]
-    fn reconstruct_huffman_from_table() {
-        let tree = basic_tree();
+/// Reads the `<length>:<comment>` field that follows the format version.
+fn read_comment(reader: &mut impl BufRead) -> Result<String, Error> {
+    let comment_len = read_len_prefix(reader)?;
 
-        let f = tree.right
-            .unwrap()
-            .branch()
-            .unwrap()
-            .right
-            .unwrap()
-            .leaf()
-            .unwrap();
+    let comment = read_prefixed(reader, comment_len, "comment")?;
 
-        assert_eq!(f, 'f');
-    }
+    String::from_utf8(comment)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "comment is not valid UTF-8"))
+}
 
-    #[test]
-    fn huffman_tree_decode_walk() {
-        let tree = basic_tree();
-        let step_1 = Root::walk(&tree, false).branch().unwrap();
-        let step_2 = Root::walk(&step_1, true).branch().unwrap();
-        let step_3 = Root::walk(&step_2, false).leaf().unwrap();
-        assert_eq!(step_3, 'h');
+/// Reads the id byte of the algorithm the codes were built with.
+fn read_algorithm(reader: &mut impl BufRead) -> Result<Algorithm, Error> {
+    let mut id = [0u8];
+    reader.read_exact(&mut id)?;
 
-        let step_1 = Root::walk(&tree, false).branch().unwrap();
-        let step_2 = Root::walk(&step_1, false).branch().unwrap();
-        let step_3 = Root::walk(&step_2, false).leaf().unwrap();
-        assert_eq!(step_3, 'n');
+    Algorithm::from_id(id[0])
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown code algorithm id {}", id[0])))
+}
 
-        let step_1 = Root::walk(&tree, true).branch().unwrap();
-        let step_2 = Root::walk(&step_1, false).branch().unwrap();
-        let step_3 = Root::walk(&step_2, true).leaf().unwrap();
-        assert_eq!(step_3, '\n');
-    }
-    
+/// Reads the byte giving the order of the bits within each payload byte.
+fn read_bit_order(reader: &mut impl BufRead) -> Result<BitOrder, Error> {
+    let mut id = [0u8];
+    reader.read_exact(&mut id)?;
 
-    #[test]
+    BitOrder::from_id(id[0])
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown payload bit order id {}", id[0])))
+}
+
+/// Payload bytes with the order of the bits within each byte reversed,
+/// which converts between the two `BitOrder`s.
+fn reverse_bit_order(payload: &[u8]) -> Vec<u8> {
+    payload.iter().map(|byte| byte.reverse_bits()).collect()
+}
+
+/// Rewrites the compressed file at `in_path` to `out_path` with its payload
+/// packed in `target` bit order, without decoding it. The header tag is updated,
+/// everything else is copied as is. Stored and model files have no payload and are copied.
+pub fn reorder_payload(in_path: impl AsRef<Path>, out_path: impl AsRef<Path>, target: BitOrder) -> Result<(), Error> {
+    let mut compressed = std::fs::read(in_path)?;
+
+    let (footer, mut body) = open_container(&compressed)?;
+
+    // offsets into `compressed` of what's left of the body
+    let body_end = compressed.len() - footer.encoded_len() as usize;
+
+    read_comment(&mut body)?;
+    read_owner(&mut body)?;
+
+    if body.starts_with(BLOCKS_TAG) || body.starts_with(DICTIONARY_TAG) {
+        return Err(Error::new(ErrorKind::InvalidInput, "the bit order of blocked and dictionary coded files can't be rewritten"));
+    }
+
+    if !(body.starts_with(STORE_TAG) || body.starts_with(MODEL_TAG)) {
+        let byte_coded = body.starts_with(BYTES_TAG);
+
+        if byte_coded {
+            body = &body[BYTES_TAG.len()..];
+        }
+
+        read_algorithm(&mut body)?;
+
+        let order_at = body_end - body.len();
+        let order = read_bit_order(&mut body)?;
+
+        match byte_coded {
+            true => {
+                read_byte_table(&mut body)?;
+            },
+            false => {
+                read_transforms(&mut body)?;
+
+                let symbol_count = read_len_prefix(&mut body)?;
+                Remap::read_from(&mut body, symbol_count)?;
+
+                read_table(&mut body)?;
+            },
+        }
+
+        let (payload, _) = read_payload(&mut body)?;
+        let payload_at = body_end - body.len() - payload.len();
+        let payload_len = payload.len();
+
+        if order != target {
+            let payload = &mut compressed[payload_at..payload_at + payload_len];
+            payload.copy_from_slice(&reverse_bit_order(payload));
+
+            compressed[order_at] = target.id();
+        }
+    }
+
+    std::fs::write(out_path, compressed)
+}
+
+/// Reads the code lengths of a model file written by `--export-model`,
+/// for compressing other inputs with their canonical codes.
+pub fn read_model(path: impl AsRef<Path>) -> Result<HashMap<char, usize>, Error> {
+    let contents = std::fs::read(path)?;
+
+    let (_, mut body) = open_container(&contents)?;
+
+    read_comment(&mut body)?;
+    read_owner(&mut body)?;
+
+    body = body
+        .strip_prefix(MODEL_TAG)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "not a model file"))?;
+
+    read_algorithm(&mut body)?;
+    read_bit_order(&mut body)?;
+    read_transforms(&mut body)?;
+
+    let symbol_count = read_len_prefix(&mut body)?;
+    Remap::read_from(&mut body, symbol_count)?;
+
+    let (_, lengths) = read_table(&mut body)?;
+
+    canonical_table(&lengths)?;
+
+    Ok(lengths)
+}
+
+/// Reads the `<count>:<ids>` list of transforms applied before coding.
+fn read_transforms(reader: &mut impl BufRead) -> Result<Vec<u8>, Error> {
+    let count = read_len_prefix(reader)?;
+
+    read_prefixed(reader, count, "transform list")
+}
+
+/// Undoes the transforms with the given ids, last applied first.
+pub(crate) fn reverse_transforms(decoded: String, ids: &[u8]) -> Result<String, Error> {
+    if ids.is_empty() {
+        return Ok(decoded);
+    }
+
+    let mut bytes = decoded.into_bytes();
+
+    for &id in ids.iter().rev() {
+        let transform = transform::by_id(id).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("unknown transform id {}", id))
+        })?;
+
+        bytes = transform.decode(&bytes);
+    }
+
+    String::from_utf8(bytes)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "transform produced invalid UTF-8"))
+}
+
+/// Reads the `<length>:<uid><gid>` owner field that follows the comment,
+/// empty unless the owner was stored.
+fn read_owner(reader: &mut impl BufRead) -> Result<Option<(u32, u32)>, Error> {
+    match read_len_prefix(reader)? {
+        0 => Ok(None),
+        8 => {
+            let mut ids = [0u8; 8];
+            reader.read_exact(&mut ids)?;
+
+            // fixed size slices of a fixed size array, unwrapping can't fail.
+            Ok(Some((
+                u32::from_le_bytes(ids[..4].try_into().unwrap()),
+                u32::from_le_bytes(ids[4..].try_into().unwrap()),
+            )))
+        },
+        _ => Err(Error::new(ErrorKind::InvalidData, "owner field is not 8 bytes long")),
+    }
+}
+
+/// Reads the `len` bytes of a length prefixed header field named `field`.
+/// The length comes from the file, so only the bytes actually there are allocated.
+fn read_prefixed(reader: &mut impl BufRead, len: usize, field: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("header declares a {} of {} bytes but only {} follow", field, len, bytes.len())
+        ));
+    }
+
+    Ok(bytes)
+}
+
+/// Reads a decimal length prefix terminated by `:`.
+pub(crate) fn read_len_prefix(reader: &mut impl BufRead) -> Result<usize, Error> {
+    let mut prefix = Vec::new();
+    reader.read_until(b':', &mut prefix)?;
+
+    prefix
+        .strip_suffix(b":")
+        .and_then(|len| std::str::from_utf8(len).ok())
+        .and_then(|len| len.parse().ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid length prefix in header"))
+}
+
+/// Metadata of a compressed file, as printed by `--inspect`.
+#[derive(Debug)]
+pub struct Inspection {
+    pub version: u8,
+    pub comment: String,
+    /// uid and gid of the original file, stored by `--preserve-owner`.
+    pub owner: Option<(u32, u32)>,
+    pub footer: Footer,
+}
+
+impl std::fmt::Display for Inspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "format version: {}", self.version)?;
+        writeln!(f, "name: {}", self.footer.name)?;
+        writeln!(f, "original length: {} bytes", self.footer.original_len)?;
+        writeln!(f, "checksum: {:08x}", self.footer.checksum)?;
+        if let Some((uid, gid)) = self.owner {
+            writeln!(f, "owner: {}:{}", uid, gid)?;
+        }
+        write!(f, "comment: {}", self.comment)
+    }
+}
+
+/// Reads the metadata of a compressed file without decoding its payload.
+pub fn inspect(path: impl AsRef<Path>) -> Result<Inspection, Error> {
+    inspect_bytes(&std::fs::read(path)?)
+}
+
+fn inspect_bytes(compressed: &[u8]) -> Result<Inspection, Error> {
+    let (footer, mut body) = open_container(compressed)?;
+
+    Ok(Inspection {
+        version: FORMAT_VERSION,
+        comment: read_comment(&mut body)?,
+        owner: read_owner(&mut body)?,
+        footer,
+    })
+}
+
+/// Whether `reader` starts with the magic bytes of a compressed file or archive,
+/// judged from its buffer without consuming anything.
+pub fn starts_compressed(reader: &mut impl BufRead) -> Result<bool, Error> {
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+/// Reads the format version of a compressed file without decoding it.
+pub fn format_version(path: impl AsRef<Path>) -> Result<u8, HuffmanError> {
+    let mut file = File::open(path)?;
+
+    read_version(&mut file)
+}
+
+/// Checks the magic bytes at the start of `reader` and returns the version byte after them.
+fn read_version(reader: &mut impl Read) -> Result<u8, HuffmanError> {
+    let mut preamble = [0u8; MAGIC.len() + 1];
+
+    match reader.read_exact(&mut preamble) {
+        Ok(()) => {},
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Err(HuffmanError::NotHuffmanFile),
+        Err(err) => return Err(err.into()),
+    }
+
+    if &preamble[..MAGIC.len()] != MAGIC {
+        return Err(HuffmanError::NotHuffmanFile);
+    }
+
+    Ok(preamble[MAGIC.len()])
+}
+
+/// Reads the footer of a compressed file without decoding it.
+pub fn read_footer(path: impl AsRef<Path>) -> Result<Footer, Error> {
+    let mut file = File::open(path)?;
+
+    Footer::read_from(&mut file)
+}
+
+/// Reads the 8 byte little endian payload length and the number of padding bits
+/// in the last payload byte.
+fn read_payload_len(body: &mut &[u8]) -> Result<(u64, u8), Error> {
+    let mut len_bytes = [0u8; 8];
+    body.read_exact(&mut len_bytes)?;
+
+    let mut padding = [0u8];
+    body.read_exact(&mut padding)?;
+
+    Ok((u64::from_le_bytes(len_bytes), padding[0]))
+}
+
+/// Reads the payload length and padding and exactly that many payload bytes,
+/// anything after them is ignored.
+fn read_payload<'a>(body: &mut &'a [u8]) -> Result<(&'a [u8], u8), Error> {
+    let (payload_len, padding) = read_payload_len(body)?;
+
+    check_payload_len(payload_len, padding, body.len())?;
+
+    let payload = &body[..payload_len as usize];
+    *body = &body[payload.len()..];
+
+    Ok((payload, padding))
+}
+
+/// Header fields cross-checked by `validate_header` before the payload is decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Table entries declared by the count line.
+    pub entry_count: usize,
+    /// Entries the table actually holds.
+    pub table_entries: usize,
+    /// Leaves of the tree rebuilt from the table,
+    /// fewer than the entries when a code is a prefix of another.
+    pub leaf_count: usize,
+    /// Longest code in the table.
+    pub max_code_len: usize,
+    /// Payload bytes declared after the table.
+    pub payload_len: u64,
+    /// Unused bits in the last payload byte.
+    pub padding: u8,
+    /// Input length from the footer, when the payload codes the input symbol for symbol.
+    pub original_len: Option<u64>,
+}
+
+/// Checks that the fields of `header` agree with each other and with the
+/// `available` bytes that follow it, so decoding never runs on a header
+/// that can't describe the payload.
+pub fn validate_header(header: &Header, available: usize) -> Result<(), HuffmanError> {
+    check_payload_len(header.payload_len, header.padding, available)?;
+
+    for found in [header.table_entries, header.leaf_count] {
+        if found != header.entry_count {
+            return Err(HuffmanError::EntryCountMismatch { declared: header.entry_count, found });
+        }
+    }
+
+    // an empty tree has no codes to walk
+    if header.entry_count == 0 && header.payload_len > 0 {
+        return Err(HuffmanError::PayloadWithoutSymbols { payload_len: header.payload_len });
+    }
+
+    if let Some(original_len) = header.original_len {
+        let payload_bits = header.payload_len * 8 - header.padding as u64;
+
+        // every symbol is at least one byte of the input and at most the longest code
+        if payload_bits > original_len.saturating_mul(header.max_code_len as u64) {
+            return Err(HuffmanError::OriginalLenMismatch { original_len, payload_bits });
+        }
+    }
+
+    Ok(())
+}
+
+/// The padding fits in the last of `payload_len` bytes and that many bytes are `available`.
+fn check_payload_len(payload_len: u64, padding: u8, available: usize) -> Result<(), HuffmanError> {
+    if padding > 7 || (payload_len == 0 && padding > 0) {
+        return Err(HuffmanError::InvalidPadding { padding, payload_len });
+    }
+
+    if payload_len > available as u64 {
+        return Err(HuffmanError::TruncatedPayload { declared: payload_len, available });
+    }
+
+    Ok(())
+}
+
+/// Decodes the payload bits up to the `padding` bits of the last byte.
+fn decode_payload(reconst: &Reconst, payload: &[u8], padding: u8) -> Result<String, Error> {
+    if reconst.huffman_tree.leaf_count() == 0 {
+        return match payload.len() {
+            0 => Ok(String::new()),
+            len => Err(HuffmanError::PayloadWithoutSymbols { payload_len: len as u64 }.into()),
+        };
+    }
+
+    let mut bv: BitVec<u8, Lsb0> = BitVec::from_slice(payload);
+    bv.truncate(bv.len() - padding as usize);
+
+    Ok(tread(&reconst.huffman_tree, bv)?)
+}
+
+/// The canonical codes of the code `lengths` in the header, as code strings.
+/// These are the encoder's codes, which are canonical too.
+fn canonical_table(lengths: &HashMap<char, usize>) -> Result<HashMap<char, String>, Error> {
+    // the tree is as deep as the longest code, crafted lengths must not reach
+    // past what any real table needs.
+    if lengths.values().any(|&len| len > MAX_CODE_LEN) {
+        return Err(HuffmanError::InvalidTree("a code length in the header is too long").into());
+    }
+
+    // lengths that over-subscribe the code space give overlapping codes
+    let kraft_sum: f64 = lengths.values().map(|&len| 0.5f64.powi(len as i32)).sum();
+
+    if kraft_sum > 1.0 {
+        return Err(HuffmanError::InvalidTree("code lengths in the header don't form a prefix code").into());
+    }
+
+    Ok(CanonicalTable::from_lengths(lengths)
+        .codes
+        .into_iter()
+        .map(|(symbol, code)| (symbol, code.iter().by_vals().map(|bit| if bit { '1' } else { '0' }).collect()))
+        .collect())
+}
+
+/// Reads the `<count>\n` line and the `<code length>:<symbol>` entries after it,
+/// returning the declared count and the code lengths.
+fn read_table(reader: &mut impl BufRead) -> Result<(usize, HashMap<char, usize>), Error> {
+    let mut lengths = HashMap::new();
+
+    let mut line = String::new();
+
+    reader.read_line(&mut line)?;
+
+    // the count line ends in `\n`, or `\r\n` after a trip through a CRLF system
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let entry_count: usize = line.parse().map_err(|_| HuffmanError::InvalidHeaderCount(line.to_string()))?;
+
+    for _ in 0..entry_count {
+        // a table cut short ends in the middle of or right before an entry
+        let (symbol, code_len) = match read_entry(reader) {
+            Ok(entry) => entry,
+            Err(_) if reader.fill_buf()?.is_empty() => {
+                return Err(HuffmanError::EntryCountMismatch { declared: entry_count, found: lengths.len() }.into());
+            },
+            Err(err) => return Err(err),
+        };
+
+        lengths.insert(symbol, code_len);
+    }
+
+    Ok((entry_count, lengths))
+}
+
+/// Reads the `<entries>:` count and the `<code length>:<byte>` entries of a byte
+/// coded file, keying the code lengths on the char of the same value as each byte.
+fn read_byte_table(reader: &mut impl BufRead) -> Result<(usize, HashMap<char, usize>), Error> {
+    let entry_count = read_len_prefix(reader)?;
+
+    let mut lengths = HashMap::new();
+
+    for _ in 0..entry_count {
+        let code_len = read_len_prefix(reader)?;
+
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+
+        lengths.insert(char::from(byte[0]), code_len);
+    }
+
+    Ok((entry_count, lengths))
+}
+
+/// Reads a single `<code length>:<symbol>` table entry.
+fn read_entry(reader: &mut impl BufRead) -> Result<(char, usize), Error> {
+    let invalid_entry = || Error::new(ErrorKind::InvalidData, "Invalid table entry in header");
+
+    let code_len = read_len_prefix(reader)?;
+
+    // the width of a UTF-8 encoded symbol is given by its leading byte.
+    let mut symbol = [0u8; 4];
+    reader.read_exact(&mut symbol[..1])?;
+
+    let symbol_len = match symbol[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Err(invalid_entry()),
+    };
+
+    reader.read_exact(&mut symbol[1..symbol_len])?;
+
+    let symbol = std::str::from_utf8(&symbol[..symbol_len])
+        .ok()
+        .and_then(|symbol| symbol.chars().next())
+        .ok_or_else(invalid_entry)?;
+
+    Ok((symbol, code_len))
+}
+
+#[derive(Debug)]
+pub struct Reconst {
+    pub encoding_table: HashMap<char, String>,
+    pub huffman_tree: Root
+}
+
+impl Reconst {
+    /// Reads the `<count>\n` line and the `<code length>:<symbol>` entries from
+    /// `reader` and rebuilds the canonical codes and their tree, leaving `reader`
+    /// at the first byte after the table.
+    /// # Errors
+    /// - `HuffmanError::InvalidHeaderCount` if the count line isn't a number
+    /// - `HuffmanError::EntryCountMismatch` if the table has fewer entries than declared,
+    ///   because it ends early or a symbol has more than one entry
+    /// - `HuffmanError::InvalidTree` if the code lengths don't form a prefix code
+    /// - `HuffmanError::Io` if an entry is malformed
+    pub fn from_reader(reader: &mut impl BufRead) -> Result<Self, DecodeError> {
+        let (entry_count, lengths) = read_table(reader)?;
+
+        if lengths.len() != entry_count {
+            return Err(HuffmanError::EntryCountMismatch { declared: entry_count, found: lengths.len() });
+        }
+
+        let encoding_table = canonical_table(&lengths)?;
+
+        Ok(Self {
+            huffman_tree: Root::from_table(&encoding_table),
+            encoding_table,
+        })
+    }
+
+    /// Rebuilds the table and tree of `raw_table` entries.
+    /// # Errors
+    /// - `HuffmanError::EntryCountMismatch` if the parsed table doesn't have `entry_count` entries,
+    ///   e.g. a truncated table
+    /// - `HuffmanError::InvalidTree` if a code is a prefix of another, no tree has both
+    pub fn from_str(entry_count: usize, raw_table: &str) -> Result<Self, DecodeError> {
+        let encoding_table = Reconst::huffman_table(raw_table);
+
+        if encoding_table.len() != entry_count {
+            return Err(HuffmanError::EntryCountMismatch { declared: entry_count, found: encoding_table.len() });
+        }
+
+        // after sorting a code that is a prefix of others sits right before one of them
+        let mut codes: Vec<&String> = encoding_table.values().collect();
+        codes.sort_unstable();
+
+        if codes.windows(2).any(|pair| pair[1].starts_with(pair[0].as_str())) {
+            return Err(HuffmanError::InvalidTree("a code is a prefix of another code"));
+        }
+
+        let huffman_tree = Root::from_table(&encoding_table);
+
+        Ok(Self {
+            encoding_table,
+            huffman_tree
+        })
+    }
+
+    /// Formats `table` as the back to back `<code length>:<symbol><code>` entries
+    /// `huffman_table` parses, sorted by symbol so the same table always gives
+    /// the same string.
+    pub fn encode_table_to_string(table: &HashMap<char, String>) -> String {
+        let mut entries: Vec<_> = table.iter().collect();
+        entries.sort_unstable_by_key(|(symbol, _)| **symbol);
+
+        entries
+            .into_iter()
+            .map(|(symbol, code)| format!("{}:{}{}", code.len(), symbol, code))
+            .collect()
+    }
+
+    /// Parses back to back `<code length>:<symbol><code>` entries,
+    /// stopping at the first malformed entry.
+    pub fn huffman_table(raw: &str) -> HashMap<char, String> {
+        let mut table = HashMap::new();
+        let mut rest = raw;
+
+        while let Some((len, entry)) = rest.split_once(':') {
+            let mut chars = entry.chars();
+
+            // the symbol is the character right after the `:`
+            // even when it is a digit, a `:` or a newline.
+            let (Ok(code_len), Some(key)) = (len.parse::<usize>(), chars.next()) else {
+                break
+            };
+
+            let tail = chars.as_str();
+
+            let Some(code) = tail.get(..code_len) else {
+                break
+            };
+
+            table.insert(key, String::from(code));
+
+            rest = &tail[code_len..];
+        }
+        table
+    }
+}
+
+/// Parses the `<count>\n` line and the `<code length>:<symbol>` entries of a
+/// text header, as they follow the symbol count in a compressed file.
+impl TryFrom<&[u8]> for Reconst {
+    type Error = DecodeError;
+
+    fn try_from(mut header: &[u8]) -> Result<Self, Self::Error> {
+        Reconst::from_reader(&mut header)
+    }
+}
+
+macro_rules! some_boxed_leaf {
+    ($e:expr) => {
+        Some(Box::new(Node::Leaf($e)))
+    };
+}
+
+macro_rules! some_boxed_branch {
+    ($e:expr) => {
+        Some(Box::new(Node::Branch($e)))
+    };
+}
+
+macro_rules! extend {
+    ($root:expr, $code:expr, $symbol_value:expr) => {
+        match &$root {
+            Some(tree) => {
+                let sub_root = tree.clone().branch().unwrap_or_else(||
+                    panic!("Failed to extend branch out of sub tree {:?}", tree)
+                );
+
+                $root = some_boxed_branch!(
+                    Root::new_traverse(Some(sub_root), &$code[1..], $symbol_value)
+                );
+            },
+            None => {
+                $root = some_boxed_branch!(
+                    Root::new_traverse(None, &$code[1..], $symbol_value)
+                );
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Root {
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+}
+
+macro_rules! walk {
+    ($sub_tree:expr, $bit_offset:expr) => {
+        match $sub_tree{
+            Some(node) => Ok(node.clone()),
+            None => Err(HuffmanError::InvalidCodePath { bit_offset: $bit_offset })
+        }
+    };
+}
+
+macro_rules! invalid_code {
+    ($invalid_char:expr) => {
+       panic!("Invalid code expected a `0` or `1`, got `{}`", $invalid_char)
+    };
+}
+
+impl Root {
+    pub fn from_table(table: &HashMap<char, String>) -> Self {
+        table.iter().fold(Root::default(), |acc, (key, value)| { 
+            Root::new_traverse(Some(acc), value, *key) 
+        })
+    }
+
+    /// Reconstructs the huffman tree through traversal of header code strings
+    /// # Panics
+    /// - On getting an invalid code other than a `0` or `1`
+    /// - On getting an empty string.
+    /// - On overwriting node leaf variant.
+    pub fn new_traverse(bootstrap: Option<Root>, code: &str, symbol_value: char) -> Self {
+        // Use the bootstrap root if it's provided.
+        let mut root = bootstrap.unwrap_or_default();
+
+        // consume string slice until reaching the last char
+        // (base condition)
+        if code.len() == 1 {
+            // its safe to unwrap here already explicitly
+            // checked that the str slice has a single character
+            let ch = code.chars().nth(0).unwrap();
+
+            match ch {
+                '1' => root.right = some_boxed_leaf!(symbol_value),
+                '0' => root.left = some_boxed_leaf!(symbol_value),
+                other_char => invalid_code!(other_char)
+            };
+
+            return root;
+        } 
+
+        match code.chars().next() {
+            Some(ch) => {
+                match ch {
+                    '1' => extend!(root.right, code, symbol_value),
+                    '0' => extend!(root.left, code, symbol_value),
+                    other_char => invalid_code!(other_char) 
+                }
+            },
+            None => panic!("Failed to traverse tree with, got empty code string.")
+        };
+
+        root
+    } 
+
+
+    /// Incremental tree traversal a tree given the next bit of the code,
+    /// which is at `bit_offset` in the payload.
+    /// # Errors
+    /// - `HuffmanError::InvalidCodePath` if the bit leads to a missing branch
+    ///   (a corrupted payload or a tree that was reconstructed incorrectly).
+    pub fn walk(root: &Root, code_elem: bool, bit_offset: usize) -> Result<Box<Node>, HuffmanError> {
+        match code_elem {
+            true => walk!(&root.right, bit_offset),
+            false => walk!(&root.left, bit_offset),
+        }
+    }
+
+    /// Rebuilds a tree from its preorder serialization.
+    ///
+    /// Every node contributes one bit in preorder: `0` for a branch, followed by
+    /// its left then its right subtree, and `1` for a leaf, which takes the next
+    /// symbol of `symbols`. Bits left over after the last leaf (byte padding) are ignored.
+    pub fn from_preorder(bits: &BitSlice<u8, Msb0>, symbols: &[char]) -> Result<Root, HuffmanError> {
+        let mut bits = bits.iter().by_vals();
+        let mut symbols = symbols.iter().copied();
+
+        let root = match Root::preorder_node(&mut bits, &mut symbols)? {
+            Node::Branch(root) => root,
+            Node::Leaf(_) => return Err(HuffmanError::InvalidTree("the root must be a branch")),
+        };
+
+        if symbols.next().is_some() {
+            return Err(HuffmanError::InvalidTree("more symbols than leaves"));
+        }
+
+        Ok(root)
+    }
+
+    fn preorder_node(
+        bits: &mut impl Iterator<Item = bool>,
+        symbols: &mut impl Iterator<Item = char>
+    ) -> Result<Node, HuffmanError> {
+        match bits.next() {
+            Some(true) => symbols
+                .next()
+                .map(Node::Leaf)
+                .ok_or(HuffmanError::InvalidTree("fewer symbols than leaves")),
+            Some(false) => {
+                let left = Root::preorder_node(bits, symbols)?;
+                let right = Root::preorder_node(bits, symbols)?;
+
+                Ok(Node::Branch(Root {
+                    left: Some(Box::new(left)),
+                    right: Some(Box::new(right)),
+                }))
+            },
+            None => Err(HuffmanError::InvalidTree("ran out of bits before the tree was complete")),
+        }
+    }
+
+    /// Counts the leaves (symbols) reachable from this root.
+    pub fn leaf_count(&self) -> usize {
+        [&self.left, &self.right]
+            .iter()
+            .map(|child| match child.as_deref() {
+                Some(Node::Branch(root)) => root.leaf_count(),
+                Some(Node::Leaf(_)) => 1,
+                None => 0,
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Branch(Root),
+    Leaf(char)
+}
+
+impl Node {
+    pub fn branch(self) -> Option<Root> {
+        match self {
+            Node::Branch(root) => Some(root),
+            Node::Leaf(_) => None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn leaf(self) -> Option<char> {
+        match self {
+            Node::Leaf(symbol) => Some(symbol),
+            Node::Branch(_) => None,
+        }
+    }
+}
+
+/// Number of bits resolved by a single `DecodeTable` lookup.
+const LOOKUP_BITS: usize = 8;
+
+/// Outcome of walking a code from the root of the tree.
+pub(crate) enum Walk {
+    /// Reached a leaf holding the symbol after consuming `len` bits.
+    Leaf(char, usize),
+    /// Ran out of bits before reaching a leaf.
+    Incomplete,
+    /// Walked into a missing child with the bit at the given index.
+    Invalid(usize),
+}
+
+pub(crate) fn walk_code(huffman_tree: &Root, bits: impl Iterator<Item = bool>) -> Walk {
+    let mut root = huffman_tree;
+
+    for (i, bit) in bits.enumerate() {
+        let child = if bit { &root.right } else { &root.left };
+
+        match child.as_deref() {
+            Some(Node::Leaf(symbol)) => return Walk::Leaf(*symbol, i + 1),
+            Some(Node::Branch(sub_root)) => root = sub_root,
+            None => return Walk::Invalid(i),
+        }
+    }
+
+    Walk::Incomplete
+}
+
+/// Lookup table that decodes codes of up to `LOOKUP_BITS` bits with a single
+/// index instead of a walk per bit, longer codes fall back to walking the tree.
+pub struct DecodeTable<'a> {
+    huffman_tree: &'a Root,
+    // indexed by the next `LOOKUP_BITS` bits of the stream, first bit in the lowest position.
+    entries: Vec<Option<(char, usize)>>,
+}
+
+impl<'a> DecodeTable<'a> {
+    pub fn new(huffman_tree: &'a Root) -> Self {
+        let entries = (0..1usize << LOOKUP_BITS)
+            .map(|index| {
+                let bits = (0..LOOKUP_BITS).map(|k| index >> k & 1 == 1);
+
+                match walk_code(huffman_tree, bits) {
+                    Walk::Leaf(symbol, len) => Some((symbol, len)),
+                    Walk::Incomplete | Walk::Invalid(_) => None,
+                }
+            })
+            .collect();
+
+        Self {
+            huffman_tree,
+            entries
+        }
+    }
+
+    /// Decodes every complete code in `code_path`.
+    /// # Errors
+    /// - `HuffmanError::InvalidCodePath` if the code path walks into a missing branch of the tree
+    pub fn decode(&self, code_path: &BitSlice<u8, Lsb0>) -> Result<String, HuffmanError> {
+        self.decode_limited(code_path, usize::MAX)
+    }
+
+    /// Decodes every complete code in `code_path`, failing as soon as
+    /// the output would grow past `max_len` bytes.
+    /// # Errors
+    /// - `HuffmanError::InvalidCodePath` if the code path walks into a missing branch of the tree
+    /// - `HuffmanError::OutputLimitExceeded` past `max_len` bytes
+    pub fn decode_limited(&self, code_path: &BitSlice<u8, Lsb0>, max_len: usize) -> Result<String, HuffmanError> {
+        let mut decoded = String::new();
+        let mut pos = 0;
+
+        while pos < code_path.len() {
+            let rest = &code_path[pos..];
+
+            let entry = if rest.len() >= LOOKUP_BITS {
+                let index = rest[..LOOKUP_BITS]
+                    .iter()
+                    .by_vals()
+                    .enumerate()
+                    .fold(0, |acc, (k, bit)| acc | (bit as usize) << k);
+
+                self.entries[index]
+            } else {
+                None
+            };
+
+            let (symbol, len) = match entry {
+                Some(hit) => hit,
+                None => match walk_code(self.huffman_tree, rest.iter().by_vals()) {
+                    Walk::Leaf(symbol, len) => (symbol, len),
+                    Walk::Incomplete => break,
+                    Walk::Invalid(i) => return Err(HuffmanError::InvalidCodePath { bit_offset: pos + i }),
+                },
+            };
+
+            if decoded.len() + symbol.len_utf8() > max_len {
+                return Err(HuffmanError::OutputLimitExceeded { limit: max_len });
+            }
+
+            decoded.push(symbol);
+            pos += len;
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Decodes the provided code slice using a `DecodeTable` built from `huffman_tree`
+/// and returns a decoded string. Every bit of `code_path` is decoded,
+/// so the padding of the last payload byte has to be cut off first.
+/// # Errors
+/// - `DecodeTable::decode` error conditions
+pub fn tread(huffman_tree: &Root, code_path: BitVec<u8, Lsb0>) -> Result<String, DecodeError> {
+    DecodeTable::new(huffman_tree).decode(&code_path)
+}
+
+/// Same as `tread` but errors instead of producing more than `max_len` bytes,
+/// a guard for decoding untrusted data.
+/// # Errors
+/// - `DecodeTable::decode_limited` error conditions
+pub fn tread_limited(huffman_tree: &Root, code_path: BitVec<u8, Lsb0>, max_len: usize) -> Result<String, HuffmanError> {
+    DecodeTable::new(huffman_tree).decode_limited(&code_path, max_len)
+}
+
+/// Push based decoder for payloads that arrive in pieces,
+/// keeping the position inside the tree between calls to `feed`.
+pub struct DecoderState<'a> {
+    huffman_tree: &'a Root,
+    position: &'a Root,
+    // bits walked since the last complete symbol
+    pending_bits: usize,
+    // bits fed so far, for the offset of an invalid code
+    fed_bits: usize,
+}
+
+impl<'a> DecoderState<'a> {
+    pub fn new(huffman_tree: &'a Root) -> Self {
+        Self {
+            huffman_tree,
+            position: huffman_tree,
+            pending_bits: 0,
+            fed_bits: 0,
+        }
+    }
+
+    /// Decodes the next payload bytes, returning the symbols completed by them.
+    /// # Errors
+    /// - `HuffmanError::InvalidCodePath` if the code path walks into a missing branch of the tree
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<char>, HuffmanError> {
+        let mut decoded = Vec::new();
+
+        for bit in bytes.view_bits::<Lsb0>().iter().by_vals() {
+            self.fed_bits += 1;
+
+            let child = if bit { &self.position.right } else { &self.position.left };
+
+            match child.as_deref() {
+                Some(Node::Leaf(symbol)) => {
+                    decoded.push(*symbol);
+                    self.position = self.huffman_tree;
+                    self.pending_bits = 0;
+                },
+                Some(Node::Branch(sub_root)) => {
+                    self.position = sub_root;
+                    self.pending_bits += 1;
+                },
+                None => return Err(HuffmanError::InvalidCodePath { bit_offset: self.fed_bits - 1 }),
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    /// Ends the stream, returning the number of bits of the unfinished
+    /// code left over, the padding of the last byte when the input is whole.
+    pub fn finish(self) -> usize {
+        self.pending_bits
+    }
+}
+
+/// Incrementally walks the huffman tree using the provided code slice
+/// and returns a decoded string, `DecodeIter` collected.
+/// # Errors
+/// - `HuffmanError::InvalidCodePath` if a bit leads to a missing child
+pub fn tread_walk(huffman_tree: &Root, code_path: BitVec<u8, Lsb0>) -> Result<String, DecodeError> {
+    DecodeIter::new(huffman_tree, code_path.into_iter()).collect()
+}
+
+/// Decodes the symbols of a bit source one at a time, yielding each as soon as
+/// its code is complete. Bits of a trailing incomplete code are ignored.
+/// After an invalid code it yields the error once and then ends.
+pub struct DecodeIter<'a, I> {
+    huffman_tree: &'a Root,
+    bits: I,
+    // bits taken from `bits` so far, for the offset of an invalid code
+    bit_offset: usize,
+    failed: bool,
+}
+
+impl<'a, I: Iterator<Item = bool>> DecodeIter<'a, I> {
+    pub fn new(huffman_tree: &'a Root, bits: I) -> Self {
+        Self {
+            huffman_tree,
+            bits,
+            bit_offset: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = bool>> Iterator for DecodeIter<'_, I> {
+    type Item = Result<char, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let mut root = self.huffman_tree;
+
+        loop {
+            let bit = self.bits.next()?;
+            let child = if bit { &root.right } else { &root.left };
+
+            self.bit_offset += 1;
+
+            match child.as_deref() {
+                Some(Node::Leaf(symbol)) => return Some(Ok(*symbol)),
+                Some(Node::Branch(sub_root)) => root = sub_root,
+                None => {
+                    self.failed = true;
+
+                    return Some(Err(HuffmanError::InvalidCodePath { bit_offset: self.bit_offset - 1 }));
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use crate::OutputTarget;
+
+    #[test]
+    fn bytes_round_trip_in_memory() {
+        let unicode = "ünïcödé 🦀 ".repeat(200);
+        let inputs: [&[u8]; 5] = [
+            b"",
+            b"a",
+            b"in memory, without touching the filesystem\n",
+            unicode.as_bytes(),
+            &[0x00, 0xff, 0xfe, 0x80, 0x01],
+        ];
+
+        for input in inputs {
+            assert_eq!(decode_bytes(&crate::encode::encode_bytes(input)).unwrap(), input);
+        }
+
+        assert!(matches!(decode_bytes(b"HUF\x00"), Err(DecodeError::UnsupportedVersion(0))));
+        assert!(matches!(decode_bytes(b"not compressed"), Err(DecodeError::NotHuffmanFile)));
+    }
+
+    #[test]
+    fn it_parses_huffman_table() {
+        let table_str = String::from("2:a013:c001");
+        let table = Reconst::huffman_table(&table_str);
+        assert_eq!(table.get(&'a').unwrap(), "01");
+        assert_eq!(table.get(&'c').unwrap(), "001");
+    }
+
+    #[test]
+    fn huffman_table_parser_handles_newlines() {
+        let table_str = String::from("2:\n01");
+
+        let table = Reconst::huffman_table(&table_str);
+
+        assert_eq!(table.get(&'\n').unwrap(), "01");
+    }
+
+    #[test]
+    fn alphabets_over_255_symbols_round_trip() {
+        let text: String = ('\u{4e00}'..'\u{5058}')
+            .flat_map(|symbol| std::iter::repeat_n(symbol, 1 + symbol as usize % 5))
+            .collect::<String>()
+            .repeat(3);
+
+        let compressed = crate::encode::encode_bytes(text.as_bytes());
+
+        assert!(!compressed.windows(STORE_TAG.len()).any(|window| window == STORE_TAG));
+        assert!(compressed.windows(4).any(|window| window == b"600\n"));
+        assert_eq!(decode_bytes(&compressed).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn malformed_tables_are_errors() {
+        let reconst = Reconst::from_str(3, "2:a013:c0011:b1").unwrap();
+        assert_eq!(reconst.huffman_tree.leaf_count(), 3);
+
+        // the last entry is cut short
+        assert!(matches!(
+            Reconst::from_str(3, "2:a013:c0011:b"),
+            Err(DecodeError::EntryCountMismatch { declared: 3, found: 2 })
+        ));
+
+        // `0` is a prefix of `01`
+        assert!(matches!(Reconst::from_str(2, "1:a02:b01"), Err(DecodeError::InvalidTree(_))));
+
+        let err = read_table(&mut &b"two\n1:a1:b"[..]).unwrap_err();
+        assert!(matches!(DecodeError::from(err), DecodeError::InvalidHeaderCount(line) if line == "two"));
+    }
+
+    #[test]
+    fn peeking_the_magic_consumes_nothing() {
+        let compressed = crate::encode::encode_bytes(b"peeked");
+
+        for (input, expected) in [(&compressed[..], true), (b"plain text", false), (b"", false)] {
+            let mut reader = input;
+
+            assert_eq!(starts_compressed(&mut reader).unwrap(), expected);
+            assert_eq!(reader, input);
+        }
+    }
+
+    #[test]
+    fn reconst_from_header_bytes() {
+        let reconst = Reconst::try_from(&b"3\n1:a2:b2:c"[..]).unwrap();
+
+        assert_eq!(reconst.encoding_table, HashMap::from([('a', "0".to_string()), ('b', "10".to_string()), ('c', "11".to_string())]));
+        assert_eq!(decode_payload(&reconst, &[0b0001_1010], 3).unwrap(), "abc");
+
+        // the bytes after the table are left to the caller
+        let mut header = &b"1\n1:\nrest"[..];
+        Reconst::from_reader(&mut header).unwrap();
+        assert_eq!(header, b"rest");
+
+        assert!(matches!(Reconst::try_from(&b"x\n1:a"[..]), Err(DecodeError::InvalidHeaderCount(_))));
+        assert!(matches!(Reconst::try_from(&b"3\n1:a2:b"[..]), Err(DecodeError::EntryCountMismatch { declared: 3, found: 2 })));
+        assert!(matches!(Reconst::try_from(&b"3\n1:a2:b2:"[..]), Err(DecodeError::EntryCountMismatch { declared: 3, found: 2 })));
+        assert!(matches!(Reconst::try_from(&b"2\n1:a1:a"[..]), Err(DecodeError::EntryCountMismatch { declared: 2, found: 1 })));
+        assert!(matches!(Reconst::try_from(&b"3\n1:a1:b1:c"[..]), Err(DecodeError::InvalidTree(_))));
+    }
+
+    #[test]
+    fn overlong_code_length_is_an_error() {
+        assert!(matches!(Reconst::try_from(&b"1\n200000:a"[..]), Err(DecodeError::InvalidTree(_))));
+
+        let mut contents = [&MAGIC[..], &[FORMAT_VERSION], b"0:0:\x00\x000:0:1\n200000:a\x01\0\0\0\0\0\0\0\0\0"].concat();
+        Footer::default().write_to(&mut contents).unwrap();
+
+        let err = decode_bytes(&contents).unwrap_err();
+        assert!(err.to_string().contains("too long"), "{}", err);
+    }
+
+    #[test]
+    fn table_count_line_may_end_in_crlf() {
+        for count_line in ["2\n", "2\r\n"] {
+            let header = format!("{}1:a1:\r", count_line);
+
+            let (entry_count, lengths) = read_table(&mut header.as_bytes()).unwrap();
+
+            assert_eq!(entry_count, 2);
+            assert_eq!(lengths, HashMap::from([('a', 1), ('\r', 1)]));
+        }
+
+        assert!(read_table(&mut &b"2\r\r\n1:a1:b"[..]).is_err());
+    }
+
+    #[test]
+    fn table_serialization_round_trips() {
+        let symbols = ['\n', '\\', ':', '0', '1', '9', '\r', '\0', ' ', 'a', 'é', '語', '🦀'];
+
+        let mut seed: u32 = 0x5e71_a11e;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as usize
+        };
+
+        for _ in 0..200 {
+            let table: HashMap<char, String> = (0..next() % symbols.len() + 1)
+                .map(|_| {
+                    let code = (0..next() % 12 + 1).map(|_| if next() % 2 == 0 { '0' } else { '1' }).collect();
+                    (symbols[next() % symbols.len()], code)
+                })
+                .collect();
+
+            let serialized = Reconst::encode_table_to_string(&table);
+
+            assert_eq!(Reconst::huffman_table(&serialized), table);
+            assert_eq!(Reconst::encode_table_to_string(&Reconst::huffman_table(&serialized)), serialized);
+        }
+    }
+
+    #[test]
+    fn header_entries_round_trip_any_symbol() {
+        let symbols = ['\n', ':', '1', '0', '\\', '\r', 'é', '語'];
+
+        let table: HashMap<char, BitVec<u8, Msb0>> = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, &symbol)| {
+                let mut code: BitVec<u8, Msb0> = BitVec::repeat(false, i);
+                code.push(true);
+                (symbol, code)
+            })
+            .collect();
+
+        let header = crate::encode::fmt_table(&table);
+
+        let parsed = Reconst::huffman_table(&header);
+
+        assert_eq!(parsed.len(), symbols.len());
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            assert_eq!(parsed[symbol], "0".repeat(i) + "1");
+        }
+
+        // code length entries read back from a stream as written
+        let header = crate::encode::fmt_code_lengths(&table);
+        let mut reader = header.as_bytes();
+
+        for _ in 0..symbols.len() {
+            let (symbol, code_len) = read_entry(&mut reader).unwrap();
+            assert_eq!(table[&symbol].len(), code_len);
+        }
+
+        assert!(reader.is_empty());
+    }
+
+
+    fn basic_tree() -> Root {
+        let table = HashMap::from([
+            ('h', "010".to_string()),
+            ('f', "11".to_string()),
+            ('m', "011".to_string()),
+            ('n', "000".to_string()),
+            ('a', "100".to_string()),
+            ('u', "001".to_string()),
+            ('\n', "101".to_string()),
+        ]);
+
+        Root::from_table(&table)
+    }
+
+    #[test]
+    fn reconstruct_huffman_from_table() {
+        let tree = basic_tree();
+
+        let f = tree.right
+            .unwrap()
+            .branch()
+            .unwrap()
+            .right
+            .unwrap()
+            .leaf()
+            .unwrap();
+
+        assert_eq!(f, 'f');
+    }
+
+    #[test]
+    fn reconstruct_huffman_from_preorder() {
+        // n u h m a \n f from left to right
+        let bits = bitvec![u8, Msb0; 0,0,0,1,1,0,1,1,0,0,1,1,1];
+        let symbols = ['n', 'u', 'h', 'm', 'a', '\n', 'f'];
+
+        let tree = Root::from_preorder(&bits, &symbols).unwrap();
+
+        assert_eq!(tree, basic_tree());
+
+        assert!(matches!(
+            Root::from_preorder(&bits[..12], &symbols),
+            Err(HuffmanError::InvalidTree(_))
+        ));
+        assert!(matches!(
+            Root::from_preorder(&bits, &symbols[..6]),
+            Err(HuffmanError::InvalidTree(_))
+        ));
+    }
+
+    #[test]
+    fn tree_leaf_count_matches_table() {
+        let tree = basic_tree();
+
+        assert_eq!(tree.leaf_count(), 7);
+        assert_eq!(Root::default().leaf_count(), 0);
+    }
+
+    #[test]
+    fn huffman_tree_decode_walk() {
+        let tree = basic_tree();
+        let step_1 = Root::walk(&tree, false, 0).unwrap().branch().unwrap();
+        let step_2 = Root::walk(&step_1, true, 0).unwrap().branch().unwrap();
+        let step_3 = Root::walk(&step_2, false, 0).unwrap().leaf().unwrap();
+        assert_eq!(step_3, 'h');
+
+        let step_1 = Root::walk(&tree, false, 0).unwrap().branch().unwrap();
+        let step_2 = Root::walk(&step_1, false, 0).unwrap().branch().unwrap();
+        let step_3 = Root::walk(&step_2, false, 0).unwrap().leaf().unwrap();
+        assert_eq!(step_3, 'n');
+
+        let step_1 = Root::walk(&tree, true, 0).unwrap().branch().unwrap();
+        let step_2 = Root::walk(&step_1, false, 0).unwrap().branch().unwrap();
+        let step_3 = Root::walk(&step_2, true, 0).unwrap().leaf().unwrap();
+        assert_eq!(step_3, '\n');
+    }
+    
+
+    #[test]
     fn huffman_tree_decode_tread_path() {
         let tree = basic_tree();
-        let decomp = tread(&tree, bitvec![u8, Lsb0; 0,1,0]);
-        assert_eq!(&decomp, "h");
+        let decomp = tread(&tree, bitvec![u8, Lsb0; 0,1,0]).unwrap();
+        assert_eq!(&decomp, "h");
+    }
+
+    #[test]
+    fn single_leaf_tree_decodes() {
+        let table = HashMap::from([('a', String::from("0"))]);
+        let tree = Root::from_table(&table);
+
+        assert_eq!(tree.leaf_count(), 1);
+        assert_eq!(tread(&tree, bitvec![u8, Lsb0; 0, 0, 0]).unwrap(), "aaa");
+        assert_eq!(tread_walk(&tree, bitvec![u8, Lsb0; 0, 0, 0]).unwrap(), "aaa");
+
+        let mut state = DecoderState::new(&tree);
+        assert_eq!(state.feed(&[0]).unwrap(), vec!['a'; 8]);
+    }
+
+    #[test]
+    fn code_path_off_the_tree_is_an_error() {
+        let table = HashMap::from([
+            ('a', String::from("0")),
+            ('b', String::from("10")),
+        ]);
+        let tree = Root::from_table(&table);
+
+        // "0" decodes to 'a', then "11" has no leaf
+        let code_path = bitvec![u8, Lsb0; 0, 1, 1];
+
+        assert!(matches!(
+            tread(&tree, code_path.clone()),
+            Err(HuffmanError::InvalidCodePath { bit_offset: 2 })
+        ));
+        assert!(matches!(
+            tread_walk(&tree, code_path),
+            Err(HuffmanError::InvalidCodePath { bit_offset: 2 })
+        ));
+    }
+
+    #[test]
+    fn decode_iter_yields_symbols_as_their_codes_complete() {
+        let table = HashMap::from([
+            ('a', String::from("0")),
+            ('b', String::from("10")),
+        ]);
+        let tree = Root::from_table(&table);
+
+        // "a b a a", then "11" has no leaf
+        let code_path = bitvec![u8, Lsb0; 0, 1, 0, 0, 0, 1, 1];
+
+        let mut taken = 0;
+        let bits = code_path.iter().by_vals().inspect(|_| taken += 1);
+
+        let prefix: String = DecodeIter::new(&tree, bits).take(2).map(Result::unwrap).collect();
+
+        // only the bits of the symbols taken were read
+        assert_eq!(prefix, "ab");
+        assert_eq!(taken, 3);
+
+        let mut iter = DecodeIter::new(&tree, code_path.iter().by_vals());
+        assert_eq!(iter.by_ref().take(4).collect::<Result<String, _>>().unwrap(), "abaa");
+        assert!(matches!(iter.next(), Some(Err(HuffmanError::InvalidCodePath { bit_offset: 6 }))));
+        assert!(iter.next().is_none());
+
+        // an incomplete last code is left out
+        let decoded: Result<String, _> = DecodeIter::new(&tree, [0, 1].map(|bit| bit == 1).into_iter()).collect();
+        assert_eq!(decoded.unwrap(), "a");
+    }
+
+    #[test]
+    fn renamed_file_restores_the_stored_name() {
+        let dir = std::env::temp_dir().join("huffman_stored_name");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let contents = "named at compression\n".repeat(30);
+        fs::write(dir.join("original.txt"), &contents).unwrap();
+
+        let config = Config { input_file: dir.join("original.txt").to_string_lossy().into_owned(), ..Config::default() };
+        crate::encode::compress(&config).unwrap();
+
+        fs::remove_file(dir.join("original.txt")).unwrap();
+        fs::rename(dir.join("original.txt.o"), dir.join("renamed.bin")).unwrap();
+
+        let config = Config {
+            input_file: dir.join("renamed.bin").to_string_lossy().into_owned(),
+            mode: crate::Mode::Decompress,
+            ..Config::default()
+        };
+        decompress(&config).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("original.txt")).unwrap(), contents);
+
+        // stored names stay in the directory of the compressed file
+        let stored = |name| stored_name_output(&config, name);
+
+        assert_eq!(stored("../../etc/passwd"), Some(dir.join("passwd")));
+        assert_eq!(stored("C:\\Users\\bird.txt"), Some(dir.join("bird.txt")));
+        assert_eq!(stored(".."), None);
+        assert_eq!(stored(""), None);
+
+        let explicit = Config { output: OutputTarget::File(dir.join("out")), ..config.clone() };
+        assert_eq!(stored_name_output(&explicit, "original.txt"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_table_with_payload_is_an_error() {
+        let input = std::env::temp_dir().join("huffman_empty_table.o");
+        let output = std::env::temp_dir().join("huffman_empty_table.txt");
+
+        let mut contents = [&MAGIC[..], &[FORMAT_VERSION], b"0:0:\x00\x000:0:0\n\x02\0\0\0\0\0\0\0\0\x2a\x17"].concat();
+        Footer::default().write_to(&mut contents).unwrap();
+
+        fs::write(&input, contents).unwrap();
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            mode: crate::Mode::Decompress,
+            ..Config::default()
+        };
+
+        let err = decompress(&config).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "empty table but nonempty payload: header declares no symbols but 2 payload bytes follow"
+        );
+        assert!(!output.exists());
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn payload_with_zero_bytes_round_trip() {
+        // the less frequent 'b' gets the code `0`, so its runs pack into 0x00 bytes
+        let contents = ("a".repeat(9) + &"b".repeat(8)).repeat(100);
+
+        let mut compressed = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+
+        let (_, mut body) = open_container(&compressed).unwrap();
+        read_comment(&mut body).unwrap();
+        read_owner(&mut body).unwrap();
+        read_algorithm(&mut body).unwrap();
+        read_bit_order(&mut body).unwrap();
+        read_transforms(&mut body).unwrap();
+        let symbol_count = read_len_prefix(&mut body).unwrap();
+        Remap::read_from(&mut body, symbol_count).unwrap();
+        read_table(&mut body).unwrap();
+
+        let (payload, _) = read_payload(&mut body).unwrap();
+        assert!(payload.contains(&0));
+
+        let mut decoded = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn padding_bits_are_not_decoded() {
+        // 'a' gets the one bit code, so zero padding would decode to extra 'a's
+        let contents = "a".repeat(200) + "bc";
+
+        let mut compressed = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+
+        let mut decoded = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        // a padding count beyond the last byte is rejected
+        let mut body: &[u8] = b"\x01\0\0\0\0\0\0\0\x08\xff";
+        assert_eq!(read_payload(&mut body).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupted_payload_fails_the_checksum() {
+        let contents = "bit rot in the middle of the payload\n".repeat(50);
+
+        let mut compressed = crate::encode::encode_bytes(contents.as_bytes());
+
+        let footer = Footer::read_from(&mut Cursor::new(&compressed)).unwrap();
+        let payload_end = compressed.len() - footer.encoded_len() as usize;
+
+        compressed[payload_end - 20] ^= 0x10;
+
+        let err = decode_bytes(&compressed).unwrap_err();
+
+        assert!(matches!(err, DecodeError::ChecksumMismatch { expected, .. } if expected == footer.checksum));
+
+        // `--no-verify` hands out whatever the payload decodes to
+        let config = Config { no_verify: true, ..Config::default() };
+        let mut decoded = Vec::new();
+
+        decompress_with(&config, &mut &compressed[..], &mut decoded).unwrap();
+
+        assert_ne!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn inconsistent_headers_are_rejected() {
+        let valid = Header {
+            entry_count: 3,
+            table_entries: 3,
+            leaf_count: 3,
+            max_code_len: 2,
+            payload_len: 2,
+            padding: 3,
+            original_len: Some(7),
+        };
+
+        assert!(validate_header(&valid, 2).is_ok());
+
+        let cases = [
+            (Header { table_entries: 2, ..valid.clone() }, 2),
+            (Header { leaf_count: 2, ..valid.clone() }, 2),
+            (valid.clone(), 1),
+            (Header { padding: 8, ..valid.clone() }, 2),
+            (Header { payload_len: 0, ..valid.clone() }, 2),
+            (Header { entry_count: 0, table_entries: 0, leaf_count: 0, ..valid.clone() }, 2),
+            (Header { original_len: Some(6), padding: 0, ..valid.clone() }, 2),
+        ];
+
+        let errors: Vec<_> = cases.iter().map(|(header, available)| validate_header(header, *available)).collect();
+
+        assert!(matches!(errors[0], Err(HuffmanError::EntryCountMismatch { declared: 3, found: 2 })));
+        assert!(matches!(errors[1], Err(HuffmanError::EntryCountMismatch { declared: 3, found: 2 })));
+        assert!(matches!(errors[2], Err(HuffmanError::TruncatedPayload { declared: 2, available: 1 })));
+        assert!(matches!(errors[3], Err(HuffmanError::InvalidPadding { padding: 8, payload_len: 2 })));
+        assert!(matches!(errors[4], Err(HuffmanError::InvalidPadding { padding: 3, payload_len: 0 })));
+        assert!(matches!(errors[5], Err(HuffmanError::PayloadWithoutSymbols { payload_len: 2 })));
+        assert!(matches!(errors[6], Err(HuffmanError::OriginalLenMismatch { original_len: 6, payload_bits: 16 })));
+
+        // transforms leave the original length unchecked
+        assert!(validate_header(&Header { original_len: None, padding: 0, ..valid }, 2).is_ok());
+    }
+
+    #[test]
+    fn empty_table_without_payload_is_empty() {
+        let mut contents = [&MAGIC[..], &[FORMAT_VERSION], b"0:0:\x00\x000:0:0\n\0\0\0\0\0\0\0\0\0"].concat();
+        Footer::default().write_to(&mut contents).unwrap();
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &contents[..], &mut decoded).unwrap();
+
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let input = std::env::temp_dir().join("huffman_footer_input.txt");
+        let compressed = std::env::temp_dir().join("huffman_footer_input.txt.o");
+        let output = std::env::temp_dir().join("huffman_footer_output.txt");
+
+        let contents = "a footer follows the payload\n".repeat(10);
+        fs::write(&input, &contents).unwrap();
+
+        let _ = fs::remove_file(&compressed);
+        let _ = fs::remove_file(&output);
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(compressed.clone()),
+            ..Config::default()
+        };
+        crate::encode::compress(&config).unwrap();
+
+        let footer = read_footer(&compressed).unwrap();
+
+        assert_eq!(footer.name, "huffman_footer_input.txt");
+        assert_eq!(footer.original_len, contents.len() as u64);
+        assert_eq!(footer.checksum, crc32fast::hash(contents.as_bytes()));
+
+        let config = Config {
+            input_file: compressed.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            mode: crate::Mode::Decompress,
+            ..Config::default()
+        };
+        decompress(&config).unwrap();
+
+        assert_eq!(fs::read_to_string(&output).unwrap(), contents);
+
+        for path in [input, compressed, output] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn tread_limited_stops_at_limit() {
+        let tree = basic_tree();
+
+        // `h` is `010`, five times over
+        let bits: BitVec<u8, Lsb0> = "010".repeat(5)
+            .chars()
+            .map(|bit| bit == '1')
+            .collect();
+
+        assert_eq!(tread_limited(&tree, bits.clone(), 5).unwrap(), "hhhhh");
+        assert!(matches!(
+            tread_limited(&tree, bits, 4),
+            Err(HuffmanError::OutputLimitExceeded { limit: 4 })
+        ));
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn latin1_round_trip() {
+        let input = std::env::temp_dir().join("huffman_latin1_input.txt");
+        let compressed = std::env::temp_dir().join("huffman_latin1_input.txt.o");
+        let output = std::env::temp_dir().join("huffman_latin1_output.txt");
+
+        // `é` and `ÿ` as single Latin-1 bytes are not valid UTF-8
+        let contents = b"caf\xe9 cr\xe8me br\xfbl\xe9e \xff\n".repeat(64);
+        fs::write(&input, &contents).unwrap();
+
+        let _ = fs::remove_file(&compressed);
+        let _ = fs::remove_file(&output);
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(compressed.clone()),
+            encoding: Some("latin1".to_string()),
+            ..Config::default()
+        };
+        crate::encode::compress(&config).unwrap();
+
+        let config = Config {
+            input_file: compressed.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            mode: crate::Mode::Decompress,
+            encoding: Some("latin1".to_string()),
+            ..Config::default()
+        };
+        decompress(&config).unwrap();
+
+        assert_eq!(fs::read(&output).unwrap(), contents);
+
+        for path in [input, compressed, output] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn writer_and_reader_round_trip() {
+        let contents = "compressed into a Vec, decompressed from a Cursor\n".repeat(40);
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut Cursor::new(compressed), &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn remapping_shrinks_sparse_alphabet_header() {
+        // 60 CJK symbols spread over the block, three bytes each in UTF-8
+        let contents: String = (0..60u32)
+            .map(|i| char::from_u32(0x4e00 + i * 37).unwrap().to_string().repeat(1 + i as usize % 5))
+            .collect::<String>()
+            .repeat(20);
+
+        let compress = |remap| {
+            let config = Config {
+                remap,
+                ..Config::default()
+            };
+
+            let mut compressed: Vec<u8> = Vec::new();
+            crate::encode::compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+            compressed
+        };
+
+        let plain = compress(false);
+        let remapped = compress(true);
+
+        // the payloads are equally long, the remapped header saves
+        // about a byte per symbol: a one byte index and delta for a three byte symbol.
+        assert!(remapped.len() + 50 <= plain.len());
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &remapped[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn decompressing_plain_text_suggests_compressing() {
+        let input = std::env::temp_dir().join("huffman_plain.txt");
+        let output = std::env::temp_dir().join("huffman_plain.txt.out");
+
+        fs::write(&input, "42\njust some plain text, never compressed\n").unwrap();
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            mode: crate::Mode::Decompress,
+            ..Config::default()
+        };
+
+        let err = decompress(&config).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "input is not a huffman file; did you mean to compress?");
+        assert!(!output.exists());
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    /// Accepts `capacity` bytes, then fails every write with `kind`.
+    struct ClosingWriter {
+        accepted: Vec<u8>,
+        capacity: usize,
+        kind: ErrorKind,
+    }
+
+    impl Write for ClosingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.accepted.len() >= self.capacity {
+                return Err(Error::new(self.kind, "reader went away"));
+            }
+
+            let len = buf.len().min(self.capacity - self.accepted.len());
+            self.accepted.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn broken_pipe_stops_cleanly() {
+        let contents = "written to a reader that leaves early\n".repeat(400);
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+
+        let config = Config {
+            write_chunk_size: 1024,
+            ..Config::default()
+        };
+
+        let mut closing = ClosingWriter { accepted: Vec::new(), capacity: 3000, kind: ErrorKind::BrokenPipe };
+        decompress_with(&config, &mut &compressed[..], &mut closing).unwrap();
+
+        assert_eq!(closing.accepted, contents.as_bytes()[..3000]);
+
+        // any other write error is still reported
+        let mut failing = ClosingWriter { accepted: Vec::new(), capacity: 3000, kind: ErrorKind::Other };
+        let err = decompress_with(&config, &mut &compressed[..], &mut failing).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Other);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn owner_is_stored_and_restored() {
+        use std::os::unix::fs::MetadataExt;
+
+        let input = std::env::temp_dir().join("huffman_owner.txt");
+        let compressed = std::env::temp_dir().join("huffman_owner.txt.o");
+        let restored = std::env::temp_dir().join("huffman_owner.restored");
+
+        fs::write(&input, "owned by whoever runs the tests\n".repeat(40)).unwrap();
+        let metadata = fs::metadata(&input).unwrap();
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            preserve_owner: true,
+            ..Config::default()
+        };
+
+        crate::encode::compress(&config).unwrap();
+
+        let inspection = inspect(&compressed).unwrap();
+
+        assert_eq!(inspection.owner, Some((metadata.uid(), metadata.gid())));
+        assert!(inspection.to_string().contains(&format!("owner: {}:{}", metadata.uid(), metadata.gid())));
+
+        // giving a file its own owner back is always permitted.
+        decompress(&Config {
+            input_file: compressed.to_string_lossy().into_owned(),
+            output: OutputTarget::File(restored.clone()),
+            mode: crate::Mode::Decompress,
+            ..Config::default()
+        }).unwrap();
+
+        let restored_metadata = fs::metadata(&restored).unwrap();
+        assert_eq!((restored_metadata.uid(), restored_metadata.gid()), (metadata.uid(), metadata.gid()));
+
+        for path in [&input, &compressed, &restored] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn denied_owner_is_an_error_only_with_force() {
+        let denied = || Err(Error::from(ErrorKind::PermissionDenied));
+
+        assert!(skip_denied(denied(), false).is_ok());
+        assert_eq!(skip_denied(denied(), true).unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        // other errors are reported either way
+        assert_eq!(skip_denied(Err(Error::from(ErrorKind::NotFound)), false).unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(skip_denied(Ok(()), true).is_ok());
+    }
+
+    #[test]
+    fn verify_round_trip_in_memory() {
+        let unicode = "ünïcödé 語 text\n".repeat(50);
+
+        let inputs: [&[u8]; 5] = [
+            b"",
+            b"\n\n\n\n\n\n\n\n\n\n\n\n",
+            b"blank lines\n\n\n\nbetween\n\n\n\nparagraphs\n\n\n\n",
+            unicode.as_bytes(),
+            &[0xff, 0x00, 0x80, 0x10, 0xff, 0xfe],
+        ];
+
+        for input in inputs {
+            assert_eq!(verify_round_trip(&Config::default(), input).unwrap(), None);
+        }
+
+        assert_eq!(first_mismatch(b"same", b"same"), None);
+        assert_eq!(first_mismatch(b"same", b"sane").unwrap().offset, 2);
+        assert_eq!(first_mismatch(b"same", b"sam").unwrap().offset, 3);
+
+        // nothing is written next to the input
+        let input = std::env::temp_dir().join("huffman_verify_round_trip.txt");
+        fs::write(&input, "\n\n\nrepeated\n\n\nnewlines\n\n\n".repeat(20)).unwrap();
+
+        let config = Config::from_iter(
+            ["huffman", "--verify", &input.to_string_lossy()].map(String::from).into_iter()
+        ).unwrap();
+
+        crate::run(&config).unwrap();
+
+        assert!(!std::env::temp_dir().join("huffman_verify_round_trip.txt.o").exists());
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn verify_against_original() {
+        let original = std::env::temp_dir().join("huffman_verify.txt");
+        let archive = std::env::temp_dir().join("huffman_verify.txt.o");
+        let altered = std::env::temp_dir().join("huffman_verify_altered.txt");
+
+        let contents = "the source is still around to compare with\n".repeat(40);
+        fs::write(&original, &contents).unwrap();
+
+        crate::encode::compress(&Config {
+            input_file: original.to_string_lossy().into_owned(),
+            ..Config::default()
+        }).unwrap();
+
+        assert_eq!(verify_against(&archive, &original).unwrap(), None);
+
+        let mut changed = contents.clone().into_bytes();
+        changed[100] = b'#';
+        fs::write(&altered, &changed).unwrap();
+
+        let mismatch = verify_against(&archive, &altered).unwrap().unwrap();
+        assert_eq!(mismatch.offset, 100);
+
+        // the context shows both sides of the differing row
+        let context = mismatch.to_string();
+        let row = context.lines().find(|line| line.starts_with('>')).unwrap();
+        assert!(row.starts_with("> 00000060"), "{}", context);
+        assert!(row.contains("23") && row.contains('#'), "{}", context);
+        assert!(context.contains("with.th"), "{}", context);
+
+        fs::write(&altered, &contents[..500]).unwrap();
+
+        assert_eq!(verify_against(&archive, &altered).unwrap().unwrap().offset, 500);
+
+        let config = Config::from_iter(
+            ["huffman", "--verify-against", &altered.to_string_lossy(), &archive.to_string_lossy()]
+                .map(String::from)
+                .into_iter()
+        ).unwrap();
+
+        let err = crate::run(&config).unwrap_err();
+        let err = err.to_string();
+        assert!(err.lines().next().unwrap().ends_with("at byte 500"));
+        assert!(err.contains("> 000001f0"), "{}", err);
+
+        for path in [&original, &archive, &altered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn reordering_twice_restores_the_file() {
+        let original = std::env::temp_dir().join("huffman_reorder.o");
+        let msb0 = std::env::temp_dir().join("huffman_reorder_msb0.o");
+        let lsb0 = std::env::temp_dir().join("huffman_reorder_lsb0.o");
+
+        let contents = "bits in the other order, same codes\n".repeat(40);
+
+        let mut compressed = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+        fs::write(&original, &compressed).unwrap();
+
+        reorder_payload(&original, &msb0, BitOrder::Msb0).unwrap();
+
+        let reordered = fs::read(&msb0).unwrap();
+        assert_eq!(reordered.len(), compressed.len());
+        assert!(reordered != compressed);
+
+        let mut decoded = Vec::new();
+        decompress_from_reader(&mut &reordered[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        reorder_payload(&msb0, &lsb0, BitOrder::Lsb0).unwrap();
+        assert_eq!(fs::read(&lsb0).unwrap(), compressed);
+
+        // stored input has no payload to reorder
+        let mut stored = Vec::new();
+        crate::encode::compress_to_writer(b"tiny", &mut stored).unwrap();
+        fs::write(&original, &stored).unwrap();
+
+        reorder_payload(&original, &msb0, BitOrder::Msb0).unwrap();
+        assert_eq!(fs::read(&msb0).unwrap(), stored);
+
+        for path in [&original, &msb0, &lsb0] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn decompress_to_temp_file() {
+        let compressed_path = std::env::temp_dir().join("huffman_to_temp.o");
+
+        let contents = "decompressed to a file nobody named\n".repeat(40);
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+        fs::write(&compressed_path, &compressed).unwrap();
+
+        // files planted at the names the first calls would pick are left alone
+        let planted: Vec<PathBuf> = (0..4)
+            .map(|n| std::env::temp_dir().join(format!("huffman-{}-{}.tmp", std::process::id(), n)))
+            .collect();
+
+        for path in &planted {
+            fs::write(path, "planted").unwrap();
+        }
+
+        let first = decompress_to_temp(&compressed_path).unwrap();
+        let second = decompress_to_temp(&compressed_path).unwrap();
+
+        assert_ne!(first, second);
+        assert!(!planted.contains(&first) && !planted.contains(&second));
+
+        assert_eq!(fs::read(&first).unwrap(), contents.as_bytes());
+
+        for path in &planted {
+            assert_eq!(fs::read(path).unwrap(), b"planted");
+            fs::remove_file(path).unwrap();
+        }
+
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+        fs::remove_file(&compressed_path).unwrap();
+    }
+
+    #[test]
+    fn best_picks_rle_for_runs() {
+        let contents = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n".repeat(100);
+
+        let config = Config {
+            best: true,
+            ..Config::default()
+        };
+
+        let mut best: Vec<u8> = Vec::new();
+        crate::encode::compress_with(&config, contents.as_bytes(), &mut best).unwrap();
+
+        let mut plain: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut plain).unwrap();
+
+        // huffman coded after RLE, the only transform id
+        assert!(best[MAGIC.len() + 1..].starts_with(b"0:0:\x00\x001:\x01"));
+        assert!(best.len() < plain.len());
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &best[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        // random bytes don't code any smaller, so they're stored
+        let mut seed: u32 = 0x5eed_1234;
+        let noise: Vec<u8> = (0..1000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8 & 0x7f
+            })
+            .collect();
+
+        let mut best: Vec<u8> = Vec::new();
+        crate::encode::compress_with(&config, &noise, &mut best).unwrap();
+
+        assert!(best[MAGIC.len() + 1..].starts_with(b"0:0:store\n"));
+    }
+
+    #[test]
+    fn header_over_cap_is_stored() {
+        // 200 distinct two byte symbols, compressible but with a large table
+        let contents: String = ('\u{100}'..'\u{1c8}').collect::<String>().repeat(20);
+
+        let compress = |max_header_bytes| {
+            let config = Config {
+                max_header_bytes,
+                ..Config::default()
+            };
+
+            let mut compressed: Vec<u8> = Vec::new();
+            crate::encode::compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+            compressed
+        };
+
+        assert!(!compress(None)[MAGIC.len() + 1..].starts_with(b"0:0:store\n"));
+        assert!(!compress(Some(4096))[MAGIC.len() + 1..].starts_with(b"0:0:store\n"));
+
+        let compressed = compress(Some(256));
+
+        assert!(compressed[MAGIC.len() + 1..].starts_with(b"0:0:store\n"));
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn stored_input_round_trip() {
+        let mut seed: u32 = 0x1234_5678;
+        let contents: Vec<u8> = (0..1000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8 & 0x7f
+            })
+            .collect();
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(&contents, &mut compressed).unwrap();
+
+        assert!(compressed[MAGIC.len() + 1..].starts_with(b"0:0:store\n"));
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents);
+
+        // dropping a stored byte no longer matches the footer
+        compressed.remove(MAGIC.len() + 1 + "0:0:".len() + STORE_TAG.len());
+
+        let err = decompress_from_reader(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_and_checks_format_version() {
+        let compressed_path = std::env::temp_dir().join("huffman_version.o");
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(b"versioned", &mut compressed).unwrap();
+
+        fs::write(&compressed_path, &compressed).unwrap();
+
+        assert_eq!(format_version(&compressed_path).unwrap(), FORMAT_VERSION);
+
+        // same file claiming a version from the future
+        compressed[MAGIC.len()] = FORMAT_VERSION + 1;
+        fs::write(&compressed_path, &compressed).unwrap();
+
+        assert_eq!(format_version(&compressed_path).unwrap(), FORMAT_VERSION + 1);
+
+        let err = decompress_from_reader(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("unsupported format version"));
+
+        fs::remove_file(&compressed_path).unwrap();
+    }
+
+    #[test]
+    fn comment_survives_round_trip() {
+        let compressed_path = std::env::temp_dir().join("huffman_comment.o");
+
+        let contents = "a file with a comment\n".repeat(40);
+
+        let config = Config {
+            comment: "nightly backup: ünïcode, colons: and all".to_string(),
+            ..Config::default()
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+        fs::write(&compressed_path, &compressed).unwrap();
+
+        let inspection = inspect(&compressed_path).unwrap();
+
+        assert_eq!(inspection.comment, config.comment);
+        assert!(inspection.to_string().contains(&format!("comment: {}", config.comment)));
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        fs::remove_file(&compressed_path).unwrap();
+    }
+
+    #[test]
+    fn oversized_comment_length_is_an_error() {
+        let mut compressed = MAGIC.to_vec();
+        compressed.push(FORMAT_VERSION);
+        compressed.extend_from_slice(b"99999999999999999:");
+        Footer::default().write_to(&mut compressed).unwrap();
+
+        let err = decode_bytes(&compressed).unwrap_err();
+        assert!(err.to_string().contains("comment of 99999999999999999 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn oversized_transform_count_is_an_error() {
+        assert_eq!(read_transforms(&mut &b"1:\x01rest"[..]).unwrap(), [1]);
+
+        let err = read_transforms(&mut &b"99999999999999999:\x01"[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn nested_blocks_are_an_error() {
+        let config = Config { block_size: Some(4), ..Config::default() };
+
+        let mut inner = Vec::new();
+        crate::encode::compress_with(&config, b"nested blocks", &mut inner).unwrap();
+        assert_eq!(decode_bytes(&inner).unwrap(), b"nested blocks");
+
+        // a blocked file whose only block is a blocked file
+        let mut outer = MAGIC.to_vec();
+        outer.push(FORMAT_VERSION);
+        outer.extend_from_slice(b"0:0:");
+        outer.extend_from_slice(BLOCKS_TAG);
+        outer.extend_from_slice(b"1:");
+        outer.extend_from_slice(&(inner.len() as u64).to_le_bytes());
+        outer.extend_from_slice(&inner);
+        Footer { original_len: 13, checksum: crc32fast::hash(b"nested blocks"), ..Footer::default() }.write_to(&mut outer).unwrap();
+
+        let err = decode_bytes(&outer).unwrap_err();
+        assert!(err.to_string().contains("split into blocks itself"), "{}", err);
+    }
+
+    #[test]
+    fn rle_then_huffman_round_trip() {
+        let contents = "aaaaaaaaaaaa bbbbbbbbbbbbbbbb cccc\n\n\n\n".repeat(40);
+
+        let config = Config {
+            transforms: vec![transform::by_name("rle").unwrap().id()],
+            ..Config::default()
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+        // huffman coded, with the transform list in the header.
+        assert!(!compressed.windows(STORE_TAG.len()).any(|window| window == STORE_TAG));
+        assert!(compressed.windows(3).any(|window| window == b"1:\x01"));
+
+        let mut plain: Vec<u8> = Vec::new();
+        crate::encode::compress_with(&Config::default(), contents.as_bytes(), &mut plain).unwrap();
+
+        assert!(compressed.len() < plain.len());
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+    }
+
+    #[test]
+    fn reads_exactly_the_declared_payload() {
+        let contents = "the payload length is in the header\n".repeat(40);
+
+        let mut compressed: Vec<u8> = Vec::new();
+        crate::encode::compress_to_writer(contents.as_bytes(), &mut compressed).unwrap();
+
+        let mut expected: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut expected).unwrap();
+
+        // junk between the payload and the footer is never read as payload
+        let footer = Footer::read_from(&mut Cursor::new(&compressed)).unwrap();
+        let footer_start = compressed.len() - footer.encoded_len() as usize;
+        compressed.splice(footer_start..footer_start, [0xff; 16]);
+
+        let mut decoded: Vec<u8> = Vec::new();
+        decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, expected);
+
+        // and a payload cut short of the declared length is an error
+        compressed.drain(footer_start - 20..footer_start + 16);
+
+        let err = decompress_from_reader(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn in_place_round_trip() {
+        let path = std::env::temp_dir().join("huffman_in_place.dat");
+
+        let contents = "compressed and restored under the same name\n".repeat(40);
+        fs::write(&path, &contents).unwrap();
+
+        let config = Config {
+            input_file: path.to_string_lossy().into_owned(),
+            in_place: true,
+            ..Config::default()
+        };
+
+        crate::encode::compress(&config).unwrap();
+
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+
+        // a second in place compression is refused
+        assert!(crate::encode::compress(&config).is_err());
+
+        decompress(&Config { mode: crate::Mode::Decompress, ..config }).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn decode_table_matches_tree_walk() {
+        // chain shaped tree with codes `1`, `01`, `001`, .. up to 12 bits,
+        // long enough to need the bit by bit fallback.
+        let table: HashMap<char, String> = ('a'..='m')
+            .enumerate()
+            .map(|(i, symbol)| {
+                let code = if symbol == 'm' {
+                    "0".repeat(12)
+                } else {
+                    "0".repeat(i) + "1"
+                };
+                (symbol, code)
+            })
+            .collect();
+
+        let tree = Root::from_table(&table);
+
+        // any bit sequence is a valid path through a full tree.
+        let mut seed: u32 = 0x2545_f491;
+        let bytes: Vec<u8> = (0..512)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                // bias toward zeros so the long codes show up
+                ((seed >> 16) as u8) & ((seed >> 8) as u8)
+            })
+            .collect();
+
+        let bits: BitVec<u8, Lsb0> = BitVec::from_vec(bytes);
+
+        let decoded = tread(&tree, bits.clone()).unwrap();
+
+        assert_eq!(decoded, tread_walk(&tree, bits).unwrap());
+        assert!(decoded.contains('m'));
+    }
+
+    #[test]
+    fn decoder_state_matches_full_decode() {
+        let tree = basic_tree();
+
+        let mut seed: u32 = 0x0bad_5eed;
+        let bytes: Vec<u8> = (0..257)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+
+        let mut state = DecoderState::new(&tree);
+        let mut streamed = String::new();
+
+        for byte in bytes.chunks(1) {
+            streamed.extend(state.feed(byte).unwrap());
+        }
+
+        let whole = tread(&tree, BitVec::from_vec(bytes.clone())).unwrap();
+
+        assert_eq!(streamed, whole);
+
+        // every code of the basic tree is 2 or 3 bits long
+        let used_bits: usize = whole.chars().map(|symbol| if symbol == 'f' { 2 } else { 3 }).sum();
+
+        assert_eq!(state.finish(), bytes.len() * 8 - used_bits);
     }
 }
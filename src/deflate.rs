@@ -0,0 +1,177 @@
+//! Experimental reader for gzip members made of a single fixed huffman
+//! DEFLATE block (RFC 1951, 3.2.6) holding only literals.
+//!
+//! Back references, stored blocks and dynamic huffman blocks aren't supported.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use bitvec::prelude::*;
+use crate::decode::{walk_code, Root, Walk};
+use crate::HuffmanError;
+use crate::encode::canonical_codes;
+
+const GZIP_ID: [u8; 2] = [0x1f, 0x8b];
+const GZIP_DEFLATE: u8 = 8;
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+/// Literal/length symbol ending a block.
+const END_OF_BLOCK: u32 = 256;
+
+/// Decodes a gzip member, checking the CRC-32 and length of its trailer.
+pub fn decode_gzip_member(member: &[u8]) -> Result<Vec<u8>, Error> {
+    let truncated = || Error::new(ErrorKind::UnexpectedEof, "gzip member is truncated");
+
+    if member.len() < 18 || member[..2] != GZIP_ID {
+        return Err(Error::new(ErrorKind::InvalidData, "not a gzip member"));
+    }
+
+    if member[2] != GZIP_DEFLATE {
+        return Err(Error::new(ErrorKind::Unsupported, "gzip member isn't DEFLATE compressed"));
+    }
+
+    let flags = member[3];
+
+    // id, method, flags, mtime, extra flags and os
+    let mut pos = 10;
+
+    if flags & FEXTRA != 0 {
+        let extra = member.get(pos..pos + 2).ok_or_else(truncated)?;
+        pos += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let terminator = member.get(pos..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or_else(truncated)?;
+            pos += terminator + 1;
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let body = member.get(pos..member.len() - 8).ok_or_else(truncated)?;
+    let trailer = &member[member.len() - 8..];
+
+    let decoded = decode_fixed_block(body)?;
+
+    // fixed size slices of a fixed size array, unwrapping can't fail.
+    let checksum = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+
+    if crc32fast::hash(&decoded) != checksum || decoded.len() as u32 != size {
+        return Err(Error::new(ErrorKind::InvalidData, "gzip trailer doesn't match the decoded data"));
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes a raw DEFLATE stream made of one final fixed huffman block of literals.
+pub fn decode_fixed_block(stream: &[u8]) -> Result<Vec<u8>, Error> {
+    let bits = stream.view_bits::<Lsb0>();
+
+    // BFINAL then the two BTYPE bits, least significant first.
+    let (is_final, block_type) = match bits.get(..3) {
+        Some(head) => (head[0], head[1] as u8 | (head[2] as u8) << 1),
+        None => return Err(Error::new(ErrorKind::UnexpectedEof, "DEFLATE stream is empty")),
+    };
+
+    if block_type != 1 {
+        return Err(Error::new(ErrorKind::Unsupported, "only fixed huffman DEFLATE blocks are supported"));
+    }
+
+    if !is_final {
+        return Err(Error::new(ErrorKind::Unsupported, "only single block DEFLATE streams are supported"));
+    }
+
+    let tree = fixed_literal_tree();
+
+    let mut decoded = Vec::new();
+    let mut pos = 3;
+
+    loop {
+        let symbol = match walk_code(&tree, bits[pos..].iter().by_vals()) {
+            Walk::Leaf(symbol, len) => {
+                pos += len;
+                symbol as u32
+            },
+            Walk::Incomplete => {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "DEFLATE block ends before its end of block code"));
+            },
+            Walk::Invalid(offset) => return Err(HuffmanError::InvalidCodePath { bit_offset: pos + offset }.into()),
+        };
+
+        match symbol {
+            0..=255 => decoded.push(symbol as u8),
+            END_OF_BLOCK => return Ok(decoded),
+            _ => {
+                return Err(Error::new(ErrorKind::Unsupported, "DEFLATE back references are not supported"));
+            },
+        }
+    }
+}
+
+/// Tree of the fixed literal/length code, symbols stored as the `char` of the same value.
+fn fixed_literal_tree() -> Root {
+    let lengths: HashMap<char, usize> = (0u32..288)
+        .map(|symbol| {
+            let len = match symbol {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            };
+
+            // below the surrogate range, unwrapping can't fail.
+            (char::from_u32(symbol).unwrap(), len)
+        })
+        .collect();
+
+    let table: HashMap<char, String> = canonical_codes(&lengths)
+        .into_iter()
+        .map(|(symbol, code)| {
+            (symbol, code.iter().by_vals().map(|bit| if bit { '1' } else { '0' }).collect())
+        })
+        .collect();
+
+    Root::from_table(&table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // `huffman, fixed codes!\n` compressed by zlib
+    const GZIP_MEMBER: &[u8] = b"\x1f\x8b\x08\0\0\0\0\0\x02\x03\
+        \xcb\x28\x4d\x4b\xcb\x4d\xcc\xd3\x51\x48\xcb\xac\x48\x4d\x51\x48\
+        \xce\x4f\x49\x2d\x56\xe4\x02\0\
+        \x42\xa2\xe6\xdb\x16\0\0\0";
+
+    #[test]
+    fn decodes_fixed_huffman_member() {
+        assert_eq!(decode_gzip_member(GZIP_MEMBER).unwrap(), b"huffman, fixed codes!\n");
+    }
+
+    #[test]
+    fn rejects_corrupt_trailer() {
+        let mut member = GZIP_MEMBER.to_vec();
+        let last = member.len() - 1;
+        member[last] ^= 1;
+
+        assert_eq!(decode_gzip_member(&member).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_back_references() {
+        // `a` repeated 20 times, all but the first a back reference
+        let stream = [0x4b, 0x4c, 0xc4, 0x04, 0x00];
+
+        assert_eq!(decode_fixed_block(&stream).unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+}
@@ -1,9 +1,15 @@
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
-use std::fs;
-use std::io::Write;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::error::Error;
-use crate::Config;
+use std::time::{Duration, Instant};
+use crate::{charset, transform, Config, InputSource, BLOCKS_TAG, BYTES_TAG, DICTIONARY_TAG, FORMAT_VERSION, MAGIC, MODEL_TAG, STORE_TAG};
+use crate::error::HuffmanError;
+use crate::footer::{Footer, FOOTER_FIXED_SIZE};
+use crate::remap::Remap;
+use crate::transform::Transform;
 use bitvec::prelude::*;
 
 
@@ -12,11 +18,14 @@ pub struct Root {
     pub left: Box<Node>, // 0
     pub right: Box<Node>, // 1
     pub frequency: u32,
+    /// Smallest symbol in the subtree, breaks frequency ties (see `Ord for Node`).
+    pub min_symbol: char,
 }
 
 impl Root {
     pub fn new(frequency: u32, left: Node, right: Node) -> Root {
         Root {
+            min_symbol: left.min_symbol().min(right.min_symbol()),
             left: Box::new(left),
             right: Box::new(right),
             frequency,
@@ -36,6 +45,7 @@ impl Default for Root {
             frequency: 0,
             left: Box::new(Node::Leaf(Symbol::default())),
             right: Box::new(Node::Leaf(Symbol::default())),
+            min_symbol: Symbol::default().value,
         }
     }
 }
@@ -97,13 +107,28 @@ impl Node {
         }
     }
 
+    /// Smallest symbol of the node: its own for a leaf, the subtree's for a branch.
+    pub fn min_symbol(&self) -> char {
+        match self {
+            Node::Branch(root) => root.min_symbol,
+            Node::Leaf(sym) => sym.value,
+        }
+    }
+
+    /// The order of nodes in the tree building queue:
+    /// by frequency, equal frequencies by their smallest symbol.
+    /// Subtrees never share a symbol, so no two queued nodes tie.
+    fn queue_key(&self) -> (u32, char) {
+        (*self.variant_freq(), self.min_symbol())
+    }
+
     /// compares the current node with another and returns a sorted in a pair tuple
     ///
     /// for **pattern matching** the pair tuple:
     ///  - the smaller node on the left (index 0)
     ///  - the bigger node on the right (index 1)
     pub fn cmp_pair(self, other: Node) -> (Node, Node){
-        if self.variant_freq() < other.variant_freq() {
+        if self.queue_key() < other.queue_key() {
             (self, other)
         } else {
             (other, self)
@@ -116,181 +141,2728 @@ impl Node {
     /// till it reaches a leaf node at this point, it adds a new entry 
     /// to the `encoding_table` **the key** is the character at the current node 
     /// and **the value** is the 'encoding_path' to the current node.
-    /// # Panics:
-    /// - Running into a 'Node Leaf' variant
+    ///
+    /// A tree that is a lone leaf (input with a single distinct symbol)
+    /// gets the 1-bit code `0`, a code can't be empty.
     pub fn generate_encoding(&self, path: BitVec<u8, Msb0>, mut encoding_table: &mut HashMap<char, BitVec<u8, Msb0>>) {
         match self {
             Node::Branch(root) => {
                 encode_child!(root.left, false, path, encoding_table);
                 encode_child!(root.right, true, path, encoding_table);
             }
-            Node::Leaf(_) => {
-                panic!("Expected a `Node::Branch` variant got a `Node::Leaf`");
+            Node::Leaf(sym) => {
+                let code = match path.is_empty() {
+                    true => bitvec![u8, Msb0; 0],
+                    false => path,
+                };
+
+                encoding_table.insert(sym.value, code);
+            }
+        }
+    }
+}
+
+/// Renders the tree one node per line, children indented under their branch
+/// and labeled with the bit leading to them. Branches show as `*` with their
+/// frequency, leaves as their quoted (escaped) symbol and frequency:
+/// ```text
+/// * 5
+///   0: * 2
+///     0: '\n' 1
+///     1: 'c' 1
+///   1: 'a' 3
+/// ```
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn render(node: &Node, f: &mut std::fmt::Formatter<'_>, depth: usize, edge: Option<char>) -> std::fmt::Result {
+            write!(f, "{:indent$}", "", indent = depth * 2)?;
+
+            if let Some(edge) = edge {
+                write!(f, "{}: ", edge)?;
+            }
+
+            match node {
+                Node::Leaf(sym) => writeln!(f, "{:?} {}", sym.value, sym.frequency),
+                Node::Branch(root) => {
+                    writeln!(f, "* {}", root.frequency)?;
+                    render(&root.left, f, depth + 1, Some('0'))?;
+                    render(&root.right, f, depth + 1, Some('1'))
+                },
+            }
+        }
+
+        render(self, f, 0, None)
+    }
+}
+
+/// `BinaryHeap` implementation depends on `Ord` and `PartialOrd` traits
+/// for managing how a value is pushed or popped from the internal data structure
+/// this implementation flips the order effectively changing the `BinaryHeap`
+/// collection from a **max heap** (the default) to a **min heap** (priority queue)
+///
+/// Tie-break: nodes of equal frequency pop in order of their smallest symbol,
+/// leaf or branch alike. The tree is then a function of the frequencies alone,
+/// so the same input always gets the same codes.
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.queue_key().cmp(&self.queue_key())
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub fn init_symbol_nodes_prio_queue(frequency_table: &HashMap<char, u32>) -> BinaryHeap<Node> {
+    // the tie-break of `Ord for Node` makes the push order irrelevant
+    frequency_table
+        .iter()
+        .map(|(&c, &freq)| Node::new_leaf(c, freq))
+        .collect()
+}
+
+/// Merges the queued nodes into a single huffman tree.
+/// # Errors
+/// - `HuffmanError::Internal` if the queue holds no nodes
+pub fn create_huffman_tree(mut prio_queue: BinaryHeap<Node>) -> Result<Node, HuffmanError> {
+    while prio_queue.len() > 1 {
+        if let (Some(n1), Some(n2)) = (prio_queue.pop(), prio_queue.pop()) {
+            // new branch frequency
+            let new_freq: u32 = n1.variant_freq() + n2.variant_freq();
+
+            let (left, right) = n1.cmp_pair(n2);
+
+            // push the new node back into the priority queue
+            prio_queue.push(
+                Node::Branch(
+                    Root::new(new_freq, left, right)
+                )
+            );
+        }
+    }
+
+    // at this point prio_queue will be dropped
+    // since this function takes ownership of the queue
+    // and will be cleaned automatically as it goes out of scope.
+    prio_queue.pop().ok_or(HuffmanError::Internal("empty priority queue, no symbols to build a tree from"))
+}
+
+pub fn generate_encoding_table(contents: &str) -> Result<HashMap<char, BitVec<u8, Msb0>>, HuffmanError> {
+    Ok(tree_encoding_table(&build_huffman_tree(contents)?))
+}
+
+/// A symbol with no code in the model it was looked up in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmappedSymbol(pub char);
+
+impl std::fmt::Display for UnmappedSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "symbol {:?} is not in the model", self.0)
+    }
+}
+
+impl Error for UnmappedSymbol {}
+
+/// What encoding does with a symbol the model has no code for,
+/// only possible with a supplied model such as `--weights`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnmappedPolicy {
+    /// Fail with `UnmappedSymbol`.
+    #[default]
+    Error,
+    /// Leave the symbol out of the output (lossy).
+    Skip,
+    /// Encode the given symbol, which has to be in the model, in its place (lossy).
+    Substitute(char),
+}
+
+impl std::str::FromStr for UnmappedPolicy {
+    type Err = &'static str;
+
+    /// Parses `error`, `skip` or `substitute=<symbol>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(UnmappedPolicy::Error),
+            "skip" => Ok(UnmappedPolicy::Skip),
+            _ => {
+                let mut escape = s
+                    .strip_prefix("substitute=")
+                    .ok_or("Expected `error`, `skip` or `substitute=<symbol>`.")?
+                    .chars();
+
+                match (escape.next(), escape.next()) {
+                    (Some(symbol), None) => Ok(UnmappedPolicy::Substitute(symbol)),
+                    _ => Err("Expected a single substitute symbol."),
+                }
+            },
+        }
+    }
+}
+
+/// How the codes are built from the symbol frequencies.
+/// Both give prefix codes, so decoding doesn't depend on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Optimal codes from merging the two least frequent nodes.
+    #[default]
+    Huffman,
+    /// Codes from recursively splitting the symbols into halves of equal frequency,
+    /// never shorter in total than huffman codes.
+    ShannonFano,
+}
+
+impl Algorithm {
+    /// Header byte of the algorithm.
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Huffman => 0,
+            Algorithm::ShannonFano => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Algorithm::Huffman),
+            1 => Some(Algorithm::ShannonFano),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Algorithm {
+    type Err = &'static str;
+
+    /// Parses `huffman` or `shannon-fano`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "huffman" => Ok(Algorithm::Huffman),
+            "shannon-fano" => Ok(Algorithm::ShannonFano),
+            _ => Err("Expected `huffman` or `shannon-fano`."),
+        }
+    }
+}
+
+/// Order of the code bits within each payload byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// The first bit of a byte is its least significant one, as written by `compress`.
+    #[default]
+    Lsb0,
+    /// The first bit of a byte is its most significant one.
+    Msb0,
+}
+
+impl BitOrder {
+    /// Header byte of the bit order.
+    pub fn id(self) -> u8 {
+        match self {
+            BitOrder::Lsb0 => 0,
+            BitOrder::Msb0 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(BitOrder::Lsb0),
+            1 => Some(BitOrder::Msb0),
+            _ => None,
+        }
+    }
+}
+
+/// Shannon-Fano codes for the given symbol frequencies: the symbols sorted by
+/// descending frequency are split where the two halves' totals are closest,
+/// the first half's codes continue with 0 and the second's with 1.
+/// A lone symbol gets the code `0`.
+pub fn shannon_fano_codes(frequencies: &HashMap<char, u32>) -> HashMap<char, BitVec<u8, Msb0>> {
+    let mut symbols: Vec<(char, u64)> = frequencies
+        .iter()
+        .map(|(&symbol, &frequency)| (symbol, frequency as u64))
+        .collect();
+
+    // ties in symbol order keep the output deterministic
+    symbols.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut table = HashMap::new();
+
+    if let [(symbol, _)] = symbols[..] {
+        table.insert(symbol, bitvec![u8, Msb0; 0]);
+        return table;
+    }
+
+    shannon_fano_split(&symbols, BitVec::new(), &mut table);
+
+    table
+}
+
+fn shannon_fano_split(symbols: &[(char, u64)], code: BitVec<u8, Msb0>, table: &mut HashMap<char, BitVec<u8, Msb0>>) {
+    match symbols {
+        [] => {},
+        [(symbol, _)] => {
+            table.insert(*symbol, code);
+        },
+        _ => {
+            let total: u64 = symbols.iter().map(|(_, frequency)| frequency).sum();
+
+            // the first split with the smallest difference between the halves
+            let mut left = 0;
+            let mut best = (u64::MAX, 1);
+
+            for split in 1..symbols.len() {
+                left += symbols[split - 1].1;
+
+                let difference = total.abs_diff(2 * left);
+
+                if difference < best.0 {
+                    best = (difference, split);
+                }
+            }
+
+            let (first, second) = symbols.split_at(best.1);
+
+            let mut zero = code.clone();
+            zero.push(false);
+            shannon_fano_split(first, zero, table);
+
+            let mut one = code;
+            one.push(true);
+            shannon_fano_split(second, one, table);
+        },
+    }
+}
+
+/// A trained model: the code of every symbol it was built from,
+/// reusable on inputs other than the one it was trained on.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    table: HashMap<char, BitVec<u8, Msb0>>,
+}
+
+impl Encoder {
+    /// Trains the model on the symbol frequencies of `contents`.
+    /// # Panics
+    /// - `contents` is empty
+    pub fn new(contents: &str) -> Self {
+        Self::from_table(generate_encoding_table(contents).expect("an encoder needs at least one symbol"))
+    }
+
+    pub fn from_table(table: HashMap<char, BitVec<u8, Msb0>>) -> Self {
+        Self { table }
+    }
+
+    pub fn table(&self) -> &HashMap<char, BitVec<u8, Msb0>> {
+        &self.table
+    }
+
+    /// Number of payload bytes `input` would take under this model,
+    /// without encoding it.
+    pub fn encoded_len(&self, input: &str) -> Result<usize, UnmappedSymbol> {
+        let mut bits = 0;
+
+        for symbol in input.chars() {
+            bits += self.table.get(&symbol).ok_or(UnmappedSymbol(symbol))?.len();
+        }
+
+        Ok(bits.div_ceil(8))
+    }
+
+    /// Canonical codes for the model's code lengths, ordered by length then symbol.
+    /// The lengths (and so the compressed size) match the model's own codes,
+    /// the bits themselves may differ.
+    pub fn codes_canonical(&self) -> impl Iterator<Item = (char, BitVec<u8, Msb0>)> {
+        let lengths: HashMap<char, usize> = self.table
+            .iter()
+            .map(|(&symbol, code)| (symbol, code.len()))
+            .collect();
+
+        canonical_codes(&lengths).into_iter()
+    }
+
+    /// Codes of the byte symbols (U+0000 to U+00FF, as byte mode keys them) indexed
+    /// by the byte: the code bits packed with the first as the most significant of
+    /// the low `len` bits, and `len`. `None` for bytes without a code and for
+    /// codes longer than 32 bits.
+    pub fn to_byte_lut(&self) -> [Option<(u32, u8)>; 256] {
+        let mut lut = [None; 256];
+
+        for (byte, entry) in (0..=u8::MAX).zip(lut.iter_mut()) {
+            *entry = self.table
+                .get(&char::from(byte))
+                .filter(|code| code.len() <= 32)
+                .map(|code| (code.iter().by_vals().fold(0, |bits, bit| bits << 1 | bit as u32), code.len() as u8));
+        }
+
+        lut
+    }
+}
+
+/// The output of `encode_into_slice` doesn't fit the buffer it was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooSmall {
+    /// Bytes the whole payload takes.
+    pub needed: usize,
+    /// Bytes the buffer holds.
+    pub capacity: usize,
+}
+
+impl std::fmt::Display for BufferTooSmall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "payload needs {} bytes but the buffer holds {}", self.needed, self.capacity)
+    }
+}
+
+impl Error for BufferTooSmall {}
+
+/// Why `encode_into_slice` couldn't encode its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceEncodeError {
+    /// The payload doesn't fit the buffer.
+    BufferTooSmall(BufferTooSmall),
+    /// The input has a symbol the model has no code for.
+    Unmapped(UnmappedSymbol),
+}
+
+impl std::fmt::Display for SliceEncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SliceEncodeError::BufferTooSmall(err) => err.fmt(f),
+            SliceEncodeError::Unmapped(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for SliceEncodeError {}
+
+impl From<BufferTooSmall> for SliceEncodeError {
+    fn from(err: BufferTooSmall) -> Self {
+        SliceEncodeError::BufferTooSmall(err)
+    }
+}
+
+impl From<UnmappedSymbol> for SliceEncodeError {
+    fn from(err: UnmappedSymbol) -> Self {
+        SliceEncodeError::Unmapped(err)
+    }
+}
+
+/// Writes code bits into a fixed buffer, in the payload's bit order
+/// (the first bit of a byte is its least significant one).
+/// The fixed size counterpart of `BitWriter`.
+struct SliceBitWriter<'a> {
+    out: &'a mut [u8],
+    bits: usize,
+}
+
+impl<'a> SliceBitWriter<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Self { out, bits: 0 }
+    }
+
+    /// Appends `code`, or leaves the writer unchanged if it doesn't fit.
+    fn write(&mut self, code: &BitSlice<u8, Msb0>) -> bool {
+        if self.bits + code.len() > self.out.len() * 8 {
+            return false;
+        }
+
+        for bit in code.iter().by_vals() {
+            let (byte, shift) = (self.bits / 8, self.bits % 8);
+
+            // the buffer isn't assumed to be zeroed
+            if shift == 0 {
+                self.out[byte] = 0;
+            }
+
+            self.out[byte] |= (bit as u8) << shift;
+            self.bits += 1;
+        }
+
+        true
+    }
+
+    fn bytes_used(&self) -> usize {
+        self.bits.div_ceil(8)
+    }
+}
+
+/// Encodes `input` with `model` into `out` without allocating,
+/// returning the number of payload bytes written.
+/// Bytes of `out` past that count are left as they were.
+/// # Errors
+/// - `SliceEncodeError::Unmapped` if `input` has a symbol the model has no code for
+/// - `SliceEncodeError::BufferTooSmall` if the payload doesn't fit `out`
+pub fn encode_into_slice(model: &Encoder, input: &str, out: &mut [u8]) -> Result<usize, SliceEncodeError> {
+    let capacity = out.len();
+    let mut writer = SliceBitWriter::new(out);
+
+    let code = |symbol: char| model.table.get(&symbol).ok_or(UnmappedSymbol(symbol));
+
+    for (i, symbol) in input.char_indices() {
+        if !writer.write(code(symbol)?) {
+            let rest = input[i..].chars().map(|symbol| code(symbol).map(|code| code.len())).sum::<Result<usize, _>>()?;
+
+            return Err(BufferTooSmall {
+                needed: (writer.bits + rest).div_ceil(8),
+                capacity,
+            }.into());
+        }
+    }
+
+    Ok(writer.bytes_used())
+}
+
+/// Builds the huffman tree from the symbol frequencies of `contents`.
+pub fn build_huffman_tree(contents: &str) -> Result<Node, HuffmanError> {
+    let frequency_table = init_frequency_table(contents);
+
+    let prio_queue = init_symbol_nodes_prio_queue(&frequency_table);
+
+    create_huffman_tree(prio_queue)
+}
+
+/// Builds the huffman tree from supplied symbol weights instead of counting an input.
+/// # Errors
+/// - `HuffmanError::Internal` if `weights` is empty
+pub fn build_weighted_tree(weights: &HashMap<char, u32>) -> Result<Node, HuffmanError> {
+    create_huffman_tree(init_symbol_nodes_prio_queue(weights))
+}
+
+/// Loads symbol weights from a JSON object mapping single char strings to
+/// non-negative integers, e.g. `{"e": 12, "t": 9, "\n": 2}`.
+pub fn load_weights(path: &Path) -> io::Result<HashMap<char, u32>> {
+    let raw: HashMap<String, u32> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("invalid weights file: {}", err)))?;
+
+    raw.into_iter()
+        .map(|(key, weight)| {
+            let mut chars = key.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(symbol), None) => Ok((symbol, weight)),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("weights file key {:?} is not a single symbol", key)
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Collects the code of every leaf of `tree` into an encoding table.
+pub fn tree_encoding_table(tree: &Node) -> HashMap<char, BitVec<u8, Msb0>> {
+    let path = bitvec!(u8, Msb0;);
+
+    let mut encoding_table = HashMap::new();
+
+    tree.generate_encoding(path, &mut encoding_table);
+
+    encoding_table
+}
+
+/// Assigns canonical huffman codes from code lengths (RFC 1951, 3.2.2):
+/// symbols are ordered by code length then by symbol, each code is the previous
+/// one plus one, shifted left whenever the length grows.
+///
+/// Symbols with a zero length get no code. The lengths are expected to
+/// come from a huffman tree, over-subscribed lengths give overlapping codes.
+pub fn canonical_codes(lengths: &HashMap<char, usize>) -> Vec<(char, BitVec<u8, Msb0>)> {
+    let mut symbols: Vec<(char, usize)> = lengths
+        .iter()
+        .filter(|(_, &len)| len > 0)
+        .map(|(&symbol, &len)| (symbol, len))
+        .collect();
+
+    symbols.sort_by_key(|&(symbol, len)| (len, symbol));
+
+    let mut codes = Vec::with_capacity(symbols.len());
+    let mut code: BitVec<u8, Msb0> = BitVec::new();
+
+    for (symbol, len) in symbols {
+        if !code.is_empty() {
+            // add one, carrying from the last bit
+            if let Some(zero) = code.last_zero() {
+                code.set(zero, true);
+                code[zero + 1..].fill(false);
+            }
+        }
+
+        code.resize(len, false);
+
+        codes.push((symbol, code.clone()));
+    }
+
+    codes
+}
+
+/// Canonical huffman codes, ordered by code length then symbol.
+/// The lengths alone give the codes, so they're all the header stores.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CanonicalTable {
+    pub codes: Vec<(char, BitVec<u8, Msb0>)>,
+}
+
+impl CanonicalTable {
+    /// The canonical codes for `lengths`, as the decoder rebuilds them.
+    pub fn from_lengths(lengths: &HashMap<char, usize>) -> Self {
+        Self { codes: canonical_codes(lengths) }
+    }
+
+    /// Code length of every symbol.
+    pub fn lengths(&self) -> HashMap<char, usize> {
+        self.codes.iter().map(|(symbol, code)| (*symbol, code.len())).collect()
+    }
+
+    /// The codes as an encoding table.
+    pub fn into_table(self) -> HashMap<char, BitVec<u8, Msb0>> {
+        self.codes.into_iter().collect()
+    }
+}
+
+/// Replaces the codes of `table` with canonical codes of the same lengths,
+/// which compress to the same size.
+pub fn canonicalize(table: &HashMap<char, BitVec<u8, Msb0>>) -> CanonicalTable {
+    let lengths: HashMap<char, usize> = table.iter().map(|(&symbol, code)| (symbol, code.len())).collect();
+
+    CanonicalTable::from_lengths(&lengths)
+}
+
+/// Writes the rendered (`Display`) tree to `out`, used by `--show-tree`.
+pub fn write_tree<W: Write>(tree: &Node, out: &mut W) -> io::Result<()> {
+    write!(out, "{}", tree)
+}
+
+/// Writes a `<symbol> <code length> <code>` line per symbol to `out` in code order,
+/// used by `--print-table`. Symbols are quoted and escaped like `'\n'`.
+pub fn write_table<W: Write>(table: &HashMap<char, BitVec<u8, Msb0>>, out: &mut W) -> io::Result<()> {
+    let mut entries: Vec<_> = table.iter().collect();
+    entries.sort_unstable_by_key(|(symbol, code)| (code.len(), **symbol));
+
+    for (symbol, code) in entries {
+        writeln!(out, "{:?} {} {}", symbol, code.len(), fmt_bitvec(code))?;
+    }
+
+    Ok(())
+}
+
+// Builds the frequency table for all of the characters of the given
+// contents string slice.
+fn init_frequency_table(contents: &str) -> HashMap<char, u32> {
+    let mut frequency_table = HashMap::new();
+    for sym in contents.chars() {
+        // Initializes table entry if doesn't exist
+        // dereferences the entry to increment it by one for each occurance
+        *frequency_table.entry(sym).or_insert(0) += 1;
+    }
+
+    frequency_table
+}
+
+/// Bytes of the input `detect_mode` looks at.
+const DETECT_SAMPLE: usize = 8 * 1024;
+
+/// Share of printable characters a sample needs to count as text.
+const TEXT_PRINTABLE_RATIO: f64 = 0.95;
+
+/// Whether an input is coded as text symbols or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolMode {
+    /// Unicode scalar values of UTF-8 (or `--encoding`) text.
+    Text,
+    /// Arbitrary bytes, coded byte by byte.
+    Binary,
+}
+
+/// Recommends a symbol mode for the file at `path` from its first bytes.
+pub fn detect_mode(path: &Path) -> io::Result<SymbolMode> {
+    // one byte past the sample tells whether the file goes on
+    let mut sample = Vec::with_capacity(DETECT_SAMPLE + 1);
+    File::open(path)?.take(DETECT_SAMPLE as u64 + 1).read_to_end(&mut sample)?;
+
+    let truncated = sample.len() > DETECT_SAMPLE;
+    sample.truncate(DETECT_SAMPLE);
+
+    Ok(symbol_mode(&sample, truncated))
+}
+
+/// Text when `sample` is UTF-8 and nearly all of it is printable, whitespace
+/// counting as printable. A `truncated` sample, the start of a longer input,
+/// may end in a character cut off by the sample.
+pub fn symbol_mode(sample: &[u8], truncated: bool) -> SymbolMode {
+    let text = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        // the sample ends in the middle of a character
+        Err(err) if truncated && err.error_len().is_none() => std::str::from_utf8(&sample[..err.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return SymbolMode::Binary,
+    };
+
+    let (mut printable, mut total) = (0, 0);
+
+    for symbol in text.chars() {
+        total += 1;
+
+        if !symbol.is_control() || symbol.is_ascii_whitespace() {
+            printable += 1;
+        }
+    }
+
+    match total == 0 || printable as f64 >= total as f64 * TEXT_PRINTABLE_RATIO {
+        true => SymbolMode::Text,
+        false => SymbolMode::Binary,
+    }
+}
+
+/// Counts every byte value of `bytes`, indexed by the byte.
+/// Byte symbols are dense enough that an array beats hashing them.
+pub fn byte_frequency_table(bytes: &[u8]) -> [u32; 256] {
+    let mut frequency_table = [0u32; 256];
+
+    for &byte in bytes {
+        frequency_table[byte as usize] += 1;
+    }
+
+    frequency_table
+}
+
+/// `HashMap` version of `byte_frequency_table`, kept as its benchmark baseline.
+pub fn byte_frequency_map(bytes: &[u8]) -> HashMap<u8, u32> {
+    let mut frequency_table = HashMap::new();
+
+    for &byte in bytes {
+        *frequency_table.entry(byte).or_insert(0) += 1;
+    }
+
+    frequency_table
+}
+
+/// Symbol statistics of a stream, gathered by `scan`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScanResult {
+    pub freqs: HashMap<char, u64>,
+    /// Number of symbols read.
+    pub total: u64,
+    /// Shannon entropy in bits per symbol.
+    pub entropy: f64,
+}
+
+/// Counts the symbols of a UTF-8 stream in one pass, holding only
+/// a fixed size buffer and the counts instead of the whole input.
+pub fn scan<R: Read>(reader: R) -> io::Result<ScanResult> {
+    let mut freqs: HashMap<char, u64> = HashMap::new();
+
+    read_utf8_chunks(reader, |text| {
+        for symbol in text.chars() {
+            *freqs.entry(symbol).or_insert(0) += 1;
+        }
+
+        Ok(())
+    })?;
+
+    let total = freqs.values().sum();
+
+    Ok(ScanResult {
+        entropy: entropy(freqs.values().copied(), total),
+        freqs,
+        total,
+    })
+}
+
+/// Reads a UTF-8 stream through a fixed size buffer,
+/// handing `f` the text of each read (chars split across reads are joined up).
+fn read_utf8_chunks<R: Read>(mut reader: R, mut f: impl FnMut(&str) -> io::Result<()>) -> io::Result<()> {
+    let mut buf = vec![0u8; 64 * 1024];
+    // bytes of a char split across two reads
+    let mut carried = 0;
+
+    loop {
+        let read = reader.read(&mut buf[carried..])?;
+        let filled = carried + read;
+
+        if read == 0 {
+            if carried > 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "stream ends inside a UTF-8 sequence"));
+            }
+
+            break;
+        }
+
+        let valid = match std::str::from_utf8(&buf[..filled]) {
+            Ok(text) => text,
+            Err(err) if err.error_len().is_none() => {
+                // only the tail is cut short, validated above.
+                std::str::from_utf8(&buf[..err.valid_up_to()]).unwrap()
+            },
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "stream is not valid UTF-8")),
+        };
+
+        f(valid)?;
+
+        let valid_len = valid.len();
+        buf.copy_within(valid_len..filled, 0);
+        carried = filled - valid_len;
+    }
+
+    Ok(())
+}
+
+/// Shannon entropy in bits per symbol of the given symbol counts.
+fn entropy(counts: impl Iterator<Item = u64>, total: u64) -> f64 {
+    counts
+        .map(|count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Estimates the size in bytes of the compressed output for `contents`
+/// from the entropy of its symbols, without building the huffman tree.
+pub fn estimate_compressed_size(contents: &str) -> usize {
+    estimate_from_frequencies(&init_frequency_table(contents))
+}
+
+/// `estimate_compressed_size` from already counted symbol frequencies.
+fn estimate_from_frequencies(frequency_table: &HashMap<char, u32>) -> usize {
+    let total: u32 = frequency_table.values().sum();
+
+    let mut header_size = format!("{}\n", frequency_table.len()).len();
+    let mut payload_bits = 0.0;
+
+    for (symbol, &frequency) in frequency_table {
+        // ideal code length of the symbol in bits, a code is at least 1 bit
+        let code_len = (-(frequency as f64 / total as f64).log2()).max(1.0);
+
+        payload_bits += code_len * frequency as f64;
+
+        // `<code length>:<symbol>` entry
+        let code_len = code_len.ceil() as usize;
+        header_size += code_len.to_string().len() + 1 + symbol.len_utf8();
+    }
+
+    header_size + (payload_bits / 8.0).ceil() as usize + FOOTER_FIXED_SIZE as usize
+}
+
+/// Whether huffman coding is expected to shrink `contents`.
+pub fn should_compress(contents: &str) -> bool {
+    estimate_compressed_size(contents) < contents.len()
+}
+
+/// Number of symbols encoded between two checks of the time limit.
+const TIME_CHECK_INTERVAL: usize = 64 * 1024;
+
+pub fn compress(config: &Config) -> Result<(), Box<dyn Error>> {
+    compress_with_stats(config).map(|_| ())
+}
+
+/// Sizes of a finished compression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionStats {
+    /// Bytes of input.
+    pub original_bytes: u64,
+    /// Bytes written, header and footer included.
+    pub compressed_bytes: u64,
+    /// Compressed size relative to the original size, 1 for empty input.
+    pub ratio: f64,
+    /// Distinct symbols of the input: chars of text, bytes of binary input.
+    pub distinct_symbols: usize,
+}
+
+impl CompressionStats {
+    pub fn new(original_bytes: u64, compressed_bytes: u64, distinct_symbols: usize) -> Self {
+        let ratio = match original_bytes {
+            0 => 1.0,
+            original_bytes => compressed_bytes as f64 / original_bytes as f64,
+        };
+
+        Self { original_bytes, compressed_bytes, ratio, distinct_symbols }
+    }
+}
+
+impl std::fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes in, {} bytes out, ratio {:.1}%, {} distinct symbols",
+            self.original_bytes,
+            self.compressed_bytes,
+            self.ratio * 100.0,
+            self.distinct_symbols
+        )
+    }
+}
+
+/// `compress` returning the sizes of the input and of the output written.
+pub fn compress_with_stats(config: &Config) -> Result<CompressionStats, Box<dyn Error>> {
+    if config.stream {
+        return compress_streaming(config);
+    }
+
+    if config.inputs.len() > 1 {
+        let mut archive = Vec::new();
+        let stats = crate::archive::compress_archive(config, &config.inputs, &mut archive)?;
+
+        config.write_output(&archive)?;
+
+        return Ok(stats);
+    }
+
+    let source = config.input_source();
+
+    // without `--text` or `--binary` the start of the input file decides.
+    let config = match (&source, config.symbols, &config.encoding) {
+        (InputSource::File(path), None, None) => Config { symbols: Some(detect_mode(path)?), ..config.clone() },
+        _ => config.clone(),
+    };
+
+    compress_from(&config, source.open()?)
+}
+
+/// `compress` with the input read from `input` instead of `config.input_file`,
+/// which still names the input in the footer. The output goes where `config` says.
+pub fn compress_from<R: Read>(config: &Config, mut input: R) -> Result<CompressionStats, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let input = bytes;
+
+    // a stream can only be sampled once it's read.
+    let config = &Config { symbols: Some(input_symbol_mode(config, &input)), ..config.clone() };
+
+    // compressing in place twice would lose track of the original.
+    if config.in_place && input.starts_with(MAGIC) {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` is already compressed", config.input_file)
+        )));
+    }
+
+    // the whole output is encoded before the output file is touched
+    // so an aborted run doesn't leave a partial file behind.
+    let mut compressed = Vec::new();
+
+    if config.self_extract {
+        compressed.extend_from_slice(self_extract_stub(&input_name(config))?.as_bytes());
+    }
+
+    compress_with(config, &input, &mut compressed)?;
+
+    let written = config.write_output(&compressed)?;
+
+    if let (true, Some(written)) = (config.self_extract, written) {
+        make_executable(&written)?;
+    }
+
+    Ok(CompressionStats::new(input.len() as u64, compressed.len() as u64, distinct_symbols(config, &input)?))
+}
+
+/// Symbol mode of an input that is already read: forced by `config`,
+/// text with `--encoding`, otherwise detected from its start.
+pub(crate) fn input_symbol_mode(config: &Config, input: &[u8]) -> SymbolMode {
+    match (config.symbols, &config.encoding) {
+        (Some(mode), _) => mode,
+        (None, Some(_)) => SymbolMode::Text,
+        (None, None) => symbol_mode(&input[..input.len().min(DETECT_SAMPLE)], input.len() > DETECT_SAMPLE),
+    }
+}
+
+/// Number of distinct symbols `input` is coded with in the symbol mode of `config`.
+pub(crate) fn distinct_symbols(config: &Config, input: &[u8]) -> io::Result<usize> {
+    if config.symbols == Some(SymbolMode::Binary) {
+        let mut seen = [false; 256];
+        input.iter().for_each(|&byte| seen[byte as usize] = true);
+
+        return Ok(seen.iter().filter(|&&seen| seen).count());
+    }
+
+    Ok(init_frequency_table(&charset::decode(input, config.encoding.as_deref())?).len())
+}
+
+/// Number of lines of the stub returned by `self_extract_stub`.
+const STUB_LINES: usize = 4;
+
+/// Shell script lines put before the compressed output by `--self-extract`,
+/// running the script pipes everything after them through `huffman --pipe -d`
+/// into `name`. The tool is looked up as `$HUFFMAN`, falling back to `huffman` on the `PATH`.
+fn self_extract_stub(name: &str) -> io::Result<String> {
+    // a newline would shift the line the payload starts on.
+    if name.is_empty() || name.contains('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't self-extract to this file name"));
+    }
+
+    let quoted = format!("'{}'", name.replace('\'', r"'\''"));
+
+    Ok(format!(
+        "#!/bin/sh\n\
+        tail -n +{} \"$0\" | \"${{HUFFMAN:-huffman}}\" --pipe -d > {} || exit 1\n\
+        exit 0\n\
+        # compressed data follows\n",
+        STUB_LINES + 1,
+        quoted
+    ))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Compresses `input` into `out` with the default settings.
+pub fn compress_to_writer<W: Write>(input: &[u8], out: &mut W) -> Result<(), Box<dyn Error>> {
+    compress_with(&Config::default(), input, out)
+}
+
+/// Compresses `input` in memory into a complete compressed file with the default settings,
+/// coding it byte by byte unless all of it is text. Never fails, whatever the bytes.
+pub fn encode_bytes(input: &[u8]) -> Vec<u8> {
+    let compress = |symbols| {
+        let config = Config { symbols: Some(symbols), ..Config::default() };
+
+        let mut compressed = Vec::new();
+        compress_with(&config, input, &mut compressed).map(|()| compressed)
+    };
+
+    // byte coding takes any input, text that can't be coded falls back to it
+    compress(symbol_mode(input, false))
+        .or_else(|_| compress(SymbolMode::Binary))
+        .expect("any bytes encode into memory")
+}
+
+/// Core of `compress`: encodes `input` into `out` according to `config`,
+/// the footer records the file name of `config.input_file`.
+pub fn compress_with<W: Write>(config: &Config, input: &[u8], out: &mut W) -> Result<(), Box<dyn Error>> {
+    if let Some(block_size) = config.block_size.filter(|&size| input.len() > size) {
+        return write_blocks(config, input, block_size, out);
+    }
+
+    if config.best {
+        return compress_best(config, input, out);
+    }
+
+    let started = Instant::now();
+
+    let footer = Footer {
+        name: input_name(config),
+        original_len: input.len() as u64,
+        checksum: crc32fast::hash(input),
+    };
+
+    // the dictionary codes text symbols, input that isn't text or has symbols
+    // the dictionary has no code for is coded on its own below.
+    if let Some(dictionary) = &config.dictionary {
+        let table = CanonicalTable::from_lengths(&crate::decode::read_model(dictionary)?).into_table();
+
+        match charset::decode(input, config.encoding.as_deref()) {
+            Ok(contents) if config.unmapped != UnmappedPolicy::Error
+                || contents.chars().all(|sym| table.contains_key(&sym)) =>
+            {
+                write_preamble(config, out)?;
+
+                return write_with_dictionary(config, dictionary, &table, &contents, footer, out, started);
+            },
+            Ok(_) => (),
+            Err(_) => {
+                write_preamble(config, out)?;
+
+                return write_bytes(config, input, &footer, out, started);
+            },
+        }
+    }
+
+    if config.symbols == Some(SymbolMode::Binary) {
+        write_preamble(config, out)?;
+
+        return write_bytes(config, input, &footer, out, started);
+    }
+
+    let contents = charset::decode(input, config.encoding.as_deref())?;
+
+    write_preamble(config, out)?;
+
+    let contents = apply_transforms(&contents, &config.transforms)?;
+
+    if config.export_model {
+        let table = build_table(config, &symbol_frequencies(config, &contents)?)?;
+
+        out.write_all(MODEL_TAG)?;
+        out.write_all(&table_header(config, &Remap::default(), &table)?)?;
+
+        footer.write_to(out)?;
+
+        return Ok(());
+    }
+
+    // the header would cost more than coding saves,
+    // store the input as is instead.
+    if !should_compress(&contents) {
+        return write_stored(out, input, &footer);
+    }
+
+    let remap = match config.remap {
+        true => Remap::from_text(&contents).unwrap_or_default(),
+        false => Remap::default(),
+    };
+
+    let contents = match remap.is_empty() {
+        true => contents,
+        false => remap.apply(&contents),
+    };
+
+    // input symbols missing from a supplied model are handled by `config.unmapped`.
+    let table = match &config.model {
+        Some(path) => {
+            let table = CanonicalTable::from_lengths(&crate::decode::read_model(path)?).into_table();
+
+            if config.print_table {
+                write_table(&table, &mut io::stderr())?;
+            }
+
+            table
+        },
+        None => build_table(config, &symbol_frequencies(config, &contents)?)?,
+    };
+
+    let header = table_header(config, &remap, &table)?;
+
+    // a symbol table over the budget falls back to storing the input.
+    if config.max_header_bytes.is_some_and(|cap| header.len() > cap) {
+        return write_stored(out, input, &footer);
+    }
+
+    // skipped and substituted symbols change what decompression gives back,
+    // the footer describes that text so it still passes the checksum.
+    let (contents, footer) = match lossy_text(&contents, &table, config.unmapped) {
+        Some(lossy) => {
+            let restored = match remap.is_empty() {
+                true => lossy.clone(),
+                false => remap.reverse(&lossy)?,
+            };
+            let restored = crate::decode::reverse_transforms(restored, &config.transforms)?;
+            let restored = charset::encode(&restored, config.encoding.as_deref())?;
+
+            let footer = Footer {
+                original_len: restored.len() as u64,
+                checksum: crc32fast::hash(&restored),
+                ..footer
+            };
+
+            (lossy, footer)
+        },
+        None => (contents, footer),
+    };
+
+    let payload = encode_payload(contents.chars(), &table, config.unmapped, started, config.time_limit)?;
+
+    out.write_all(&header)?;
+
+    payload.write_to(out)?;
+
+    footer.write_to(out)?;
+
+    Ok(())
+}
+
+/// `contents` with the symbols `table` has no code for skipped or substituted
+/// as `unmapped` says, `None` when nothing is lost.
+fn lossy_text(contents: &str, table: &HashMap<char, BitVec<u8, Msb0>>, unmapped: UnmappedPolicy) -> Option<String> {
+    if unmapped == UnmappedPolicy::Error || contents.chars().all(|sym| table.contains_key(&sym)) {
+        return None;
+    }
+
+    let lossy = contents.chars().filter_map(|sym| match (table.contains_key(&sym), unmapped) {
+        (true, _) => Some(sym),
+        (false, UnmappedPolicy::Substitute(escape)) => Some(escape),
+        (false, _) => None,
+    });
+
+    Some(lossy.collect())
+}
+
+/// Same output as `compress_with` on the UTF-8 encoding of `chars`, for callers
+/// that already hold the text as chars: both the counting and the encoding pass
+/// run over the slice instead of decoding UTF-8 twice.
+/// Options that rewrite the text (`--encoding`, `--best`, transforms, remapping)
+/// or supply the model (weights and model files) go through `compress_with` instead.
+pub fn compress_chars<W: Write>(config: &Config, chars: &[char], out: &mut W) -> Result<(), Box<dyn Error>> {
+    if config.best
+        || config.remap
+        || config.weights.is_some()
+        || config.model.is_some()
+        || config.export_model
+        || config.encoding.is_some()
+        || !config.transforms.is_empty()
+    {
+        return compress_with(config, chars.iter().collect::<String>().as_bytes(), out);
+    }
+
+    let started = Instant::now();
+
+    let mut frequency_table = HashMap::new();
+    let mut hasher = crc32fast::Hasher::new();
+    let mut len = 0;
+
+    let mut utf8 = [0u8; 4];
+
+    for &symbol in chars {
+        let bytes = symbol.encode_utf8(&mut utf8).as_bytes();
+
+        hasher.update(bytes);
+        len += bytes.len();
+
+        *frequency_table.entry(symbol).or_insert(0) += 1;
+    }
+
+    let footer = Footer {
+        name: input_name(config),
+        original_len: len as u64,
+        checksum: hasher.finalize(),
+    };
+
+    write_preamble(config, out)?;
+
+    let stored = || chars.iter().collect::<String>();
+
+    if estimate_from_frequencies(&frequency_table) >= len {
+        return write_stored(out, stored().as_bytes(), &footer);
+    }
+
+    let table = build_table(config, &frequency_table)?;
+
+    let header = table_header(config, &Remap::default(), &table)?;
+
+    if config.max_header_bytes.is_some_and(|cap| header.len() > cap) {
+        return write_stored(out, stored().as_bytes(), &footer);
+    }
+
+    let payload = encode_payload(chars.iter().copied(), &table, config.unmapped, started, config.time_limit)?;
+
+    out.write_all(&header)?;
+
+    payload.write_to(out)?;
+
+    footer.write_to(out)?;
+
+    Ok(())
+}
+
+/// Symbol frequencies the codes are built from: the `--weights` file or the counted `contents`.
+fn symbol_frequencies(config: &Config, contents: &str) -> io::Result<HashMap<char, u32>> {
+    match &config.weights {
+        Some(path) => load_weights(Path::new(path)),
+        None => Ok(init_frequency_table(contents)),
+    }
+}
+
+/// Canonical codes for `frequencies` built with `config.algorithm`,
+/// printing the tree first for `--show-tree`.
+/// Empty input has no symbols and gets an empty table.
+fn build_table(config: &Config, frequencies: &HashMap<char, u32>) -> Result<HashMap<char, BitVec<u8, Msb0>>, HuffmanError> {
+    if frequencies.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let table = match config.algorithm {
+        Algorithm::Huffman => {
+            let tree = build_weighted_tree(frequencies)?;
+
+            if config.show_tree {
+                write_tree(&tree, &mut io::stderr())?;
+            }
+
+            tree_encoding_table(&tree)
+        },
+        Algorithm::ShannonFano => shannon_fano_codes(frequencies),
+    };
+
+    let table = canonicalize(&table).into_table();
+
+    if config.print_table {
+        write_table(&table, &mut io::stderr())?;
+    }
+
+    Ok(table)
+}
+
+/// Header fields from the algorithm id up to the symbol table: the algorithm and
+/// bit order bytes, `<count>:<ids>`, the symbol mapping, `<entries>\n` and the
+/// code length entries the decoder rebuilds the canonical codes from.
+fn table_header(config: &Config, remap: &Remap, table: &HashMap<char, BitVec<u8, Msb0>>) -> io::Result<Vec<u8>> {
+    let mut header = vec![config.algorithm.id(), BitOrder::default().id()];
+    header.extend_from_slice(format!("{}:", config.transforms.len()).as_bytes());
+    header.extend_from_slice(&config.transforms);
+    remap.write_to(&mut header)?;
+    header.extend_from_slice(format!("{}\n", table.len()).as_bytes());
+    header.extend_from_slice(fmt_code_lengths(table).as_bytes());
+
+    Ok(header)
+}
+
+/// Compresses a UTF-8 stream in two passes without holding it in memory:
+/// the first counts the symbols, the second seeks back to the start and encodes.
+/// Always huffman codes, without the store fallback, transforms, remapping or `--encoding`.
+pub fn compress_seekable<R: Read + Seek, W: Write>(config: &Config, reader: R, out: &mut W) -> Result<(), Box<dyn Error>> {
+    compress_seekable_with_progress(config, reader, out, |_| {})
+}
+
+/// `compress_seekable` reporting the progress of the encoding pass to `on_progress`
+/// after each read of the input, and once more when the output is complete.
+pub fn compress_seekable_with_progress<R: Read + Seek, W: Write>(
+    config: &Config,
+    mut reader: R,
+    out: &mut W,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let first_pass = count_pass(&mut reader)?;
+
+    reader.seek(SeekFrom::Start(0))?;
+
+    encode_pass(config, first_pass, reader, out, started, &mut on_progress)?;
+
+    Ok(())
+}
+
+/// Cumulative byte counts of a streaming compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Input bytes encoded so far.
+    pub bytes_in: u64,
+    /// Length of the whole input, known from the counting pass.
+    pub total_in: u64,
+    /// Output bytes written so far, header included.
+    pub bytes_out: u64,
+}
+
+impl Progress {
+    /// Output size relative to the input size so far, 1 before any input.
+    pub fn ratio(&self) -> f64 {
+        match self.bytes_in {
+            0 => 1.0,
+            bytes_in => self.bytes_out as f64 / bytes_in as f64,
+        }
+    }
+
+    /// Percentage of the input encoded so far, 100 for an empty input.
+    pub fn percent(&self) -> f64 {
+        match self.total_in {
+            0 => 100.0,
+            total_in => self.bytes_in as f64 * 100.0 / total_in as f64,
+        }
+    }
+}
+
+/// Writer counting the bytes written through it.
+struct CountedWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `compress` for inputs too big to buffer, used by `--stream`: two passes over
+/// the input file, seeking back for the second one or re-opening the file when
+/// it can't seek (a FIFO). The output is written while encoding.
+pub fn compress_streaming(config: &Config) -> Result<CompressionStats, Box<dyn Error>> {
+    let path = config.get_input_file();
+    let started = Instant::now();
+
+    let mut file = File::open(&path)?;
+    let first_pass = count_pass(&mut file)?;
+
+    let file = match file.seek(SeekFrom::Start(0)) {
+        Ok(_) => file,
+        Err(_) => File::open(&path)?,
+    };
+
+    let mut on_progress = |progress: Progress| {
+        if config.progress && !config.quiet {
+            eprint!(
+                "\r{:5.1}% ({} of {} bytes in, {} bytes out, ratio {:.1}%)",
+                progress.percent(),
+                progress.bytes_in,
+                progress.total_in,
+                progress.bytes_out,
+                progress.ratio() * 100.0
+            );
+        }
+    };
+
+    let (original_bytes, distinct_symbols) = (first_pass.len, first_pass.freqs.len());
+
+    let compressed_bytes = config.write_output_with(|out| {
+        encode_pass(config, first_pass, file, out, started, &mut on_progress)
+    })?;
+
+    if config.progress && !config.quiet {
+        eprintln!();
+    }
+
+    Ok(CompressionStats::new(original_bytes, compressed_bytes, distinct_symbols))
+}
+
+/// What the counting pass of the two pass compressor learns about the input.
+struct FirstPass {
+    freqs: HashMap<char, u64>,
+    len: u64,
+    checksum: u32,
+}
+
+/// Reader hashing and counting the bytes read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        self.hasher.update(&buf[..read]);
+        self.len += read as u64;
+
+        Ok(read)
+    }
+}
+
+fn count_pass<R: Read>(reader: R) -> io::Result<FirstPass> {
+    let mut hashing = HashingReader {
+        inner: reader,
+        hasher: crc32fast::Hasher::new(),
+        len: 0,
+    };
+
+    let freqs = scan(&mut hashing)?.freqs;
+
+    Ok(FirstPass {
+        freqs,
+        len: hashing.len,
+        checksum: hashing.hasher.finalize(),
+    })
+}
+
+/// Second pass of the two pass compressor, returns the number of bytes written.
+fn encode_pass<R: Read, W: Write + ?Sized>(
+    config: &Config,
+    first_pass: FirstPass,
+    reader: R,
+    out: &mut W,
+    started: Instant,
+    on_progress: &mut dyn FnMut(Progress),
+) -> Result<u64, Box<dyn Error>> {
+    let frequency_table = first_pass.freqs
+        .iter()
+        .map(|(&symbol, &count)| u32::try_from(count).map(|count| (symbol, count)))
+        .collect::<Result<HashMap<char, u32>, _>>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "a symbol occurs too often to count"))?;
+
+    let table = build_table(config, &frequency_table)?;
+
+    let payload_bits: u64 = first_pass.freqs
+        .iter()
+        .map(|(symbol, &count)| count * table[symbol].len() as u64)
+        .sum();
+
+    let out = &mut CountedWriter { inner: out, written: 0 };
+
+    write_preamble(config, out)?;
+
+    // `--stream` allows neither transforms nor remapping
+    out.write_all(&table_header(config, &Remap::default(), &table)?)?;
+    write_payload_len(out, payload_bits)?;
+
+    let mut payload = BitWriter::new(&mut *out);
+    let (mut bytes_in, total_in) = (0, first_pass.len);
+
+    read_utf8_chunks(reader, |text| {
+        check_time_limit(started, config.time_limit)?;
+
+        for symbol in text.chars() {
+            let code = table.get(&symbol).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "the input changed between the two passes")
+            })?;
+
+            payload.write_bits(code)?;
+        }
+
+        bytes_in += text.len() as u64;
+        on_progress(Progress { bytes_in, total_in, bytes_out: payload.get_ref().written });
+
+        Ok(())
+    })?;
+
+    payload.finish()?;
+
+    let footer = Footer {
+        name: input_name(config),
+        original_len: first_pass.len,
+        checksum: first_pass.checksum,
+    };
+
+    footer.write_to(out)?;
+
+    on_progress(Progress { bytes_in, total_in, bytes_out: out.written });
+
+    Ok(out.written)
+}
+
+/// File name of the input as recorded in the footer.
+fn input_name(config: &Config) -> String {
+    match config.input_source() {
+        InputSource::File(path) => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        InputSource::Stdin => String::new(),
+    }
+}
+
+/// Writes the magic and version bytes, the comment and the owner field.
+fn write_preamble<W: Write>(config: &Config, out: &mut W) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[FORMAT_VERSION])?;
+    out.write_all(format!("{}:{}", config.comment.len(), config.comment).as_bytes())?;
+
+    match config.preserve_owner {
+        true => {
+            let (uid, gid) = input_owner(&config.get_input_file())?;
+
+            out.write_all(b"8:")?;
+            out.write_all(&uid.to_le_bytes())?;
+            out.write_all(&gid.to_le_bytes())
+        },
+        false => out.write_all(b"0:"),
+    }
+}
+
+/// uid and gid of the file at `path`.
+#[cfg(unix)]
+fn input_owner(path: &Path) -> io::Result<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+
+    Ok((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn input_owner(_path: &Path) -> io::Result<(u32, u32)> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "'--preserve-owner' is only supported on Unix"))
+}
+
+/// Compresses `input` as plain huffman, RLE then huffman and stored,
+/// writing whichever output is the smallest. The header of each records the strategy.
+fn compress_best<W: Write>(config: &Config, input: &[u8], out: &mut W) -> Result<(), Box<dyn Error>> {
+    let strategies = [
+        Config { transforms: Vec::new(), ..config.clone() },
+        Config { transforms: vec![transform::Rle.id()], ..config.clone() },
+        // a zero byte header budget always stores
+        Config { max_header_bytes: Some(0), ..config.clone() },
+    ];
+
+    let mut smallest: Option<Vec<u8>> = None;
+
+    for strategy in strategies {
+        let mut candidate = Vec::new();
+
+        // a strategy that can't handle the input (e.g. `--weights` missing the RLE counts) is skipped.
+        if compress_with(&Config { best: false, ..strategy }, input, &mut candidate).is_err() {
+            continue;
+        }
+
+        if smallest.as_ref().is_none_or(|smallest| candidate.len() < smallest.len()) {
+            smallest = Some(candidate);
+        }
+    }
+
+    match smallest {
+        Some(smallest) => Ok(out.write_all(&smallest)?),
+        // storing only fails on errors shared by every strategy, report them.
+        None => compress_with(&Config { best: false, max_header_bytes: Some(0), ..config.clone() }, input, out),
+    }
+}
+
+fn write_stored<W: Write>(out: &mut W, input: &[u8], footer: &Footer) -> Result<(), Box<dyn Error>> {
+    out.write_all(STORE_TAG)?;
+    out.write_all(input)?;
+
+    footer.write_to(out)?;
+
+    Ok(())
+}
+
+/// Codes `contents` with `table`, read from the `dictionary` model file: `DICTIONARY_TAG`,
+/// the 4 byte little endian CRC-32 of the model file and the payload. The table
+/// stays in the model file, so small inputs aren't outweighed by their header.
+fn write_with_dictionary<W: Write>(
+    config: &Config,
+    dictionary: &str,
+    table: &HashMap<char, BitVec<u8, Msb0>>,
+    contents: &str,
+    footer: Footer,
+    out: &mut W,
+    started: Instant,
+) -> Result<(), Box<dyn Error>> {
+    if config.print_table {
+        write_table(table, &mut io::stderr())?;
+    }
+
+    // as in `compress_with`, the footer describes the text that decompresses
+    let lossy = lossy_text(contents, table, config.unmapped);
+
+    let footer = match &lossy {
+        Some(lossy) => {
+            let restored = charset::encode(lossy, config.encoding.as_deref())?;
+
+            Footer { original_len: restored.len() as u64, checksum: crc32fast::hash(&restored), ..footer }
+        },
+        None => footer,
+    };
+
+    let contents = lossy.as_deref().unwrap_or(contents);
+
+    let payload = encode_payload(contents.chars(), table, config.unmapped, started, config.time_limit)?;
+
+    out.write_all(DICTIONARY_TAG)?;
+    out.write_all(&crate::decode::dictionary_id(dictionary)?.to_le_bytes())?;
+
+    payload.write_to(out)?;
+
+    footer.write_to(out)?;
+
+    Ok(())
+}
+
+/// Codes `input` in blocks of about `block_size` bytes: `BLOCKS_TAG`, `<count>:`,
+/// then per block its 8 byte little endian length and the block coded by `compress_with`
+/// as a compressed file of its own, with a table fitted to that block alone.
+fn write_blocks<W: Write>(config: &Config, input: &[u8], block_size: usize, out: &mut W) -> Result<(), Box<dyn Error>> {
+    let blocks = split_blocks(input, block_size);
+
+    write_preamble(config, out)?;
+
+    out.write_all(BLOCKS_TAG)?;
+    out.write_all(format!("{}:", blocks.len()).as_bytes())?;
+
+    for block in blocks {
+        // the name, comment and owner are only stored once, in the outer file
+        let block_config = Config {
+            input_file: String::new(),
+            comment: String::new(),
+            preserve_owner: false,
+            block_size: None,
+            symbols: Some(input_symbol_mode(config, block)),
+            ..config.clone()
+        };
+
+        let mut coded = Vec::new();
+        compress_with(&block_config, block, &mut coded)?;
+
+        out.write_all(&(coded.len() as u64).to_le_bytes())?;
+        out.write_all(&coded)?;
+    }
+
+    Footer {
+        name: input_name(config),
+        original_len: input.len() as u64,
+        checksum: crc32fast::hash(input),
+    }.write_to(out)?;
+
+    Ok(())
+}
+
+/// Splits `input` into blocks of `block_size` bytes, moving the end of a block
+/// back before the continuation bytes of a UTF-8 char so text blocks stay text.
+fn split_blocks(input: &[u8], block_size: usize) -> Vec<&[u8]> {
+    let mut blocks = Vec::new();
+    let mut rest = input;
+
+    while rest.len() > block_size {
+        let mut end = block_size;
+
+        while end > 0 && block_size - end < 3 && rest[end] & 0xc0 == 0x80 {
+            end -= 1;
+        }
+
+        if end == 0 {
+            end = block_size;
+        }
+
+        let (block, tail) = rest.split_at(end);
+        blocks.push(block);
+        rest = tail;
+    }
+
+    blocks.push(rest);
+
+    blocks
+}
+
+/// Codes `input` byte by byte: `BYTES_TAG`, the algorithm and bit order bytes,
+/// `<entries>:`, the `<code length>:<byte>` entries and the payload.
+/// Input that coding wouldn't shrink is stored instead.
+fn write_bytes<W: Write>(config: &Config, input: &[u8], footer: &Footer, out: &mut W, started: Instant) -> Result<(), Box<dyn Error>> {
+    let frequencies = byte_frequency_table(input);
+
+    let symbols = frequencies.iter().filter(|&&frequency| frequency > 0).count();
+
+    // a lone byte value has no branch to take a code from
+    if symbols < 2 || estimate_from_frequencies(&byte_symbols(&frequencies)) >= input.len() {
+        return write_stored(out, input, footer);
+    }
+
+    let table = byte_encoding_table(config, &frequencies)?;
+
+    out.write_all(BYTES_TAG)?;
+    out.write_all(&[config.algorithm.id(), BitOrder::default().id()])?;
+    out.write_all(format!("{}:", symbols).as_bytes())?;
+
+    for byte in (0..=u8::MAX).filter(|byte| table.contains_key(byte)) {
+        out.write_all(format!("{}:", table[&byte].len()).as_bytes())?;
+        out.write_all(&[byte])?;
+    }
+
+    // the counts give the payload length up front, so it's written while encoding
+    let payload_bits: u64 = (0..=u8::MAX)
+        .filter(|byte| table.contains_key(byte))
+        .map(|byte| frequencies[byte as usize] as u64 * table[&byte].len() as u64)
+        .sum();
+
+    write_payload_len(out, payload_bits)?;
+
+    let lut = Encoder::from_table(table.iter().map(|(&byte, code)| (char::from(byte), code.clone())).collect()).to_byte_lut();
+
+    let mut payload = BitWriter::new(&mut *out);
+
+    for (i, byte) in input.iter().enumerate() {
+        if i % TIME_CHECK_INTERVAL == 0 {
+            check_time_limit(started, config.time_limit)?;
+        }
+
+        match lut[*byte as usize] {
+            Some((bits, len)) => payload.write_packed(bits, len)?,
+            None => payload.write_bits(&table[byte])?,
+        }
+    }
+
+    payload.finish()?;
+
+    footer.write_to(out)?;
+
+    Ok(())
+}
+
+/// Codes for the bytes counted in `frequencies`, built with `config.algorithm`.
+/// The code builders key symbols on `char`, so each byte goes through them
+/// as the char of the same value (U+0000 to U+00FF).
+pub fn byte_encoding_table(config: &Config, frequencies: &[u32; 256]) -> Result<HashMap<u8, BitVec<u8, Msb0>>, HuffmanError> {
+    let table = build_table(config, &byte_symbols(frequencies))?;
+
+    Ok(table.into_iter().map(|(symbol, code)| (symbol as u8, code)).collect())
+}
+
+/// The counted bytes of `frequencies` as char symbols of the same value.
+fn byte_symbols(frequencies: &[u32; 256]) -> HashMap<char, u32> {
+    (0..=u8::MAX)
+        .filter(|&byte| frequencies[byte as usize] > 0)
+        .map(|byte| (char::from(byte), frequencies[byte as usize]))
+        .collect()
+}
+
+/// Runs `text` through the transforms with the given ids, in order.
+fn apply_transforms(text: &str, ids: &[u8]) -> io::Result<String> {
+    let mut bytes = text.as_bytes().to_vec();
+
+    for &id in ids {
+        let transform = transform::by_id(id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("unknown transform id {}", id))
+        })?;
+
+        bytes = transform.encode(&bytes);
+    }
+
+    String::from_utf8(bytes).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, "transform produced invalid UTF-8")
+    })
+}
+
+/// Packed codes of the input and how many of their bits are codes,
+/// the rest of the last byte is padding.
+struct Payload {
+    bytes: Vec<u8>,
+    bits: u64,
+}
+
+impl Payload {
+    fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_payload_len(out, self.bits)?;
+        out.write_all(&self.bytes)
+    }
+}
+
+/// Writes the 8 byte little endian payload length in bytes of a payload of `bits` bits,
+/// followed by the number of padding bits in its last byte.
+fn write_payload_len<W: Write>(out: &mut W, bits: u64) -> io::Result<()> {
+    out.write_all(&bits.div_ceil(8).to_le_bytes())?;
+    out.write_all(&[(bits.div_ceil(8) * 8 - bits) as u8])
+}
+
+/// Packs codes into bytes the way the payload is read: the first bit of a byte
+/// is its lowest bit. Every completed byte goes straight to the inner writer,
+/// so only the bits of the last, partial byte are held.
+pub struct BitWriter<W: Write> {
+    inner: W,
+    // bits of the partial byte, from the lowest bit up
+    byte: u8,
+    filled: u32,
+    bits: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, byte: 0, filled: 0, bits: 0 }
+    }
+
+    pub fn write_bits(&mut self, bits: &BitSlice<u8, Msb0>) -> io::Result<()> {
+        for bit in bits.iter().by_vals() {
+            self.byte |= (bit as u8) << self.filled;
+            self.filled += 1;
+
+            if self.filled == 8 {
+                self.inner.write_all(&[self.byte])?;
+                self.byte = 0;
+                self.filled = 0;
+            }
+        }
+
+        self.bits += bits.len() as u64;
+
+        Ok(())
+    }
+
+    /// Writes the low `len` bits of `bits`, most significant first,
+    /// the packed codes of `Encoder::to_byte_lut`.
+    pub fn write_packed(&mut self, bits: u32, len: u8) -> io::Result<()> {
+        for shift in (0..len).rev() {
+            self.byte |= (((bits >> shift) & 1) as u8) << self.filled;
+            self.filled += 1;
+
+            if self.filled == 8 {
+                self.inner.write_all(&[self.byte])?;
+                self.byte = 0;
+                self.filled = 0;
+            }
+        }
+
+        self.bits += len as u64;
+
+        Ok(())
+    }
+
+    /// Number of bits written so far.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes the partial last byte, padded with zero bits,
+    /// returning the inner writer and the number of bits written.
+    pub fn finish(mut self) -> io::Result<(W, u64)> {
+        if self.filled > 0 {
+            self.inner.write_all(&[self.byte])?;
+        }
+
+        Ok((self.inner, self.bits))
+    }
+}
+
+/// Packs the codes of every symbol in `contents` into payload bytes,
+/// checking `time_limit` (measured from `started`) every `TIME_CHECK_INTERVAL` symbols.
+/// Symbols missing from `table` are handled according to `unmapped`.
+fn encode_payload(
+    contents: impl Iterator<Item = char>,
+    table: &HashMap<char, BitVec<u8, Msb0>>,
+    unmapped: UnmappedPolicy,
+    started: Instant,
+    time_limit: Option<Duration>
+) -> io::Result<Payload> {
+    // held in memory since the payload length goes first and
+    // skipped symbols aren't known until they're seen.
+    let mut payload = BitWriter::new(Vec::new());
+
+    for (i, sym) in contents.enumerate() {
+        if i % TIME_CHECK_INTERVAL == 0 {
+            check_time_limit(started, time_limit)?;
+        }
+
+        let code = match (table.get(&sym), unmapped) {
+            (Some(code), _) => code,
+            (None, UnmappedPolicy::Skip) => continue,
+            (None, UnmappedPolicy::Substitute(escape)) => table.get(&escape).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, UnmappedSymbol(escape))
+            })?,
+            (None, UnmappedPolicy::Error) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, UnmappedSymbol(sym)));
+            },
+        };
+
+        payload.write_bits(code)?;
+    }
+
+    let (bytes, bits) = payload.finish()?;
+
+    Ok(Payload { bytes, bits })
+}
+
+fn check_time_limit(started: Instant, time_limit: Option<Duration>) -> io::Result<()> {
+    match time_limit {
+        Some(limit) if started.elapsed() > limit => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("compression exceeded the time limit of {}s", limit.as_secs_f64())
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Writes `payload` to `out` in writes of at most `chunk_size` bytes,
+/// trading syscall overhead against the size of each write.
+/// # Panics
+/// - `chunk_size` is zero
+pub fn write_chunked<W: Write>(out: &mut W, payload: &[u8], chunk_size: usize) -> io::Result<()> {
+    for chunk in payload.chunks(chunk_size) {
+        out.write_all(chunk)?;
+    }
+
+    out.flush()
+}
+
+/// Formats the encoding table as `<code length>:<symbol><code>` entries
+/// written back to back, the length prefix keeps every symbol (newlines included)
+/// unambiguous without a line terminator. Format versions before 10 stored
+/// this in the header, `Reconst::huffman_table` parses it.
+pub fn fmt_table(table: &HashMap<char, BitVec<u8, Msb0>>) -> String {
+    let table: HashMap<char, String> = table.iter().map(|(&symbol, bits)| (symbol, fmt_bitvec(bits))).collect();
+
+    crate::decode::Reconst::encode_table_to_string(&table)
+}
+
+/// Formats the code lengths of the table as the header entries `<code length>:<symbol>`
+/// written back to back, sorted by symbol.
+pub fn fmt_code_lengths(table: &HashMap<char, BitVec<u8, Msb0>>) -> String {
+    let mut entries: Vec<_> = table.iter().collect();
+    entries.sort_unstable_by_key(|(symbol, _)| **symbol);
+
+    entries
+        .into_iter()
+        .map(|(symbol, code)| format!("{}:{}", code.len(), symbol))
+        .collect()
+}
+
+fn fmt_bitvec(bits: &BitSlice<u8, Msb0>) -> String {
+    let mut code = String::new();
+    for bit in bits.iter().by_vals() {
+        match bit {
+            true => code.push('1'),
+            false => code.push('0')
+        }
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::OutputTarget;
+
+    #[test]
+    fn canonical_codes_match_rfc_1951_example() {
+        let lengths: HashMap<char, usize> = [
+            ('A', 3), ('B', 3), ('C', 3), ('D', 3), ('E', 3), ('F', 2), ('G', 4), ('H', 4)
+        ].into_iter().collect();
+
+        let codes: Vec<(char, String)> = canonical_codes(&lengths)
+            .into_iter()
+            .map(|(symbol, code)| (symbol, fmt_bitvec(&code)))
+            .collect();
+
+        let expected = [
+            ('F', "00"), ('A', "010"), ('B', "011"), ('C', "100"),
+            ('D', "101"), ('E', "110"), ('G', "1110"), ('H', "1111")
+        ].map(|(symbol, code)| (symbol, code.to_string()));
+
+        assert_eq!(codes, expected);
+    }
+
+    #[test]
+    fn same_input_compresses_to_the_same_bytes() {
+        // every symbol ties with others, each frequency table iterates in its own order
+        let contents = "abcdefghijklmnopqrstuvwxyz0123456789".repeat(3);
+
+        let first = encode_bytes(contents.as_bytes());
+
+        for _ in 0..8 {
+            assert_eq!(encode_bytes(contents.as_bytes()), first);
+            assert_eq!(generate_encoding_table(&contents).unwrap(), generate_encoding_table(&contents).unwrap());
+        }
+    }
+
+    #[test]
+    fn blocks_get_a_table_each() {
+        // the halves share no symbols, one table needs 2 bit codes where each half needs 1 bit
+        let contents = format!("{}{}", "ab".repeat(500), "日本".repeat(500));
+        let block_size = 1000;
+
+        let config = Config { block_size: Some(block_size), ..Config::default() };
+
+        let mut blocked = Vec::new();
+        compress_with(&config, contents.as_bytes(), &mut blocked).unwrap();
+
+        assert!(blocked.windows(BLOCKS_TAG.len()).any(|window| window == BLOCKS_TAG));
+        assert!(blocked.len() < encode_bytes(contents.as_bytes()).len());
+        assert_eq!(crate::decode::decode_bytes(&blocked).unwrap(), contents.as_bytes());
+
+        // inputs no longer than a block aren't split
+        let mut single = Vec::new();
+        compress_with(&Config { block_size: Some(contents.len()), ..Config::default() }, contents.as_bytes(), &mut single).unwrap();
+        assert_eq!(single, encode_bytes(contents.as_bytes()));
+    }
+
+    #[test]
+    fn blocks_end_on_char_boundaries() {
+        let text = "aé日€b".repeat(10);
+
+        for block_size in 1..8 {
+            let blocks = split_blocks(text.as_bytes(), block_size);
+
+            assert_eq!(blocks.concat(), text.as_bytes());
+            assert!(blocks.iter().all(|block| block.len() <= block_size));
+            assert!(block_size < 3 || blocks.iter().all(|block| std::str::from_utf8(block).is_ok()));
+        }
+    }
+
+    #[test]
+    fn canonical_codes_are_prefix_free() {
+        let contents = "canonical codes only depend on the code lengths\n".repeat(4);
+
+        let lengths: HashMap<char, usize> = generate_encoding_table(&contents)
+            .unwrap()
+            .into_iter()
+            .map(|(symbol, code)| (symbol, code.len()))
+            .collect();
+
+        let codes = canonical_codes(&lengths);
+
+        assert_eq!(codes.len(), lengths.len());
+
+        for (symbol, code) in &codes {
+            assert_eq!(code.len(), lengths[symbol]);
+
+            for (other, other_code) in &codes {
+                if symbol != other {
+                    assert!(!other_code.starts_with(code));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shannon_fano_codes_are_never_shorter() {
+        let contents = "shannon and fano split the symbols in halves\n".repeat(4);
+        let frequencies = init_frequency_table(&contents);
+
+        let huffman = generate_encoding_table(&contents).unwrap();
+        let shannon_fano = shannon_fano_codes(&frequencies);
+
+        assert_eq!(shannon_fano.len(), huffman.len());
+
+        for (symbol, code) in &shannon_fano {
+            for (other, other_code) in &shannon_fano {
+                if symbol != other {
+                    assert!(!other_code.starts_with(code));
+                }
+            }
+        }
+
+        let bits = |table: &HashMap<char, BitVec<u8, Msb0>>| -> usize {
+            frequencies.iter().map(|(symbol, &frequency)| table[symbol].len() * frequency as usize).sum()
+        };
+
+        assert!(bits(&shannon_fano) >= bits(&huffman));
+
+        // the classic example where shannon-fano loses: 15, 7, 6, 6, 5
+        let classic = HashMap::from([('a', 15), ('b', 7), ('c', 6), ('d', 6), ('e', 5)]);
+        let lengths = |table: HashMap<char, BitVec<u8, Msb0>>| -> Vec<usize> {
+            "abcde".chars().map(|symbol| table[&symbol].len()).collect()
+        };
+
+        assert_eq!(lengths(shannon_fano_codes(&classic)), [2, 2, 2, 3, 3]);
+        assert_eq!(lengths(tree_encoding_table(&build_weighted_tree(&classic).unwrap())), [1, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn shannon_fano_round_trip() {
+        let contents = "shannon and fano split the symbols in halves\n".repeat(40);
+
+        let config = Config { algorithm: Algorithm::ShannonFano, ..Config::default() };
+
+        let mut compressed = Vec::new();
+        compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+        let mut huffman = Vec::new();
+        compress_with(&Config::default(), contents.as_bytes(), &mut huffman).unwrap();
+
+        assert!(compressed != huffman);
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        let mut chars = Vec::new();
+        compress_chars(&config, &contents.chars().collect::<Vec<_>>(), &mut chars).unwrap();
+
+        assert!(chars == compressed);
+    }
+
+    #[test]
+    fn weights_file_sets_the_codes() {
+        let weights_path = std::env::temp_dir().join("huffman_weights.json");
+
+        // the opposite of the input's own frequencies
+        fs::write(&weights_path, r#"{"z": 1000, "y": 100, "x": 10, "a": 1, "\n": 1}"#).unwrap();
+
+        let weights = load_weights(&weights_path).unwrap();
+        let table = tree_encoding_table(&build_weighted_tree(&weights).unwrap());
+
+        assert_eq!(table[&'z'].len(), 1);
+        assert!(table[&'y'].len() < table[&'x'].len());
+        assert!(table[&'x'].len() < table[&'a'].len());
+
+        let contents = "aaaaaaaaaaaaaaaaaaaaxxxxxyz\n".repeat(40);
+
+        let config = Config {
+            weights: Some(weights_path.to_string_lossy().into_owned()),
+            ..Config::default()
+        };
+
+        let mut weighted = Vec::new();
+        compress_with(&config, contents.as_bytes(), &mut weighted).unwrap();
+
+        // the header holds the weighted code lengths, not the counted ones.
+        let header = String::from_utf8_lossy(&weighted);
+        assert!(header.contains(&fmt_code_lengths(&table)));
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &weighted[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        // symbols without a weight can't be encoded
+        let unweighted = "b".repeat(400) + "a\n";
+        assert!(compress_with(&config, unweighted.as_bytes(), &mut Vec::new()).is_err());
+
+        fs::remove_file(&weights_path).unwrap();
+    }
+
+    #[test]
+    fn codes_rebuilt_from_frequencies_match() {
+        // mostly equal frequencies, so the tie-break shapes the tree
+        let contents = "abcdefgh".repeat(3) + "ijkl\n語";
+
+        let frequencies = init_frequency_table(&contents);
+        let codes = generate_encoding_table(&contents).unwrap();
+
+        // queued in reverse symbol order the tree comes out the same
+        let mut symbols: Vec<_> = frequencies.iter().collect();
+        symbols.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut queue = BinaryHeap::new();
+        for (&symbol, &frequency) in symbols {
+            queue.push(Node::new_leaf(symbol, frequency));
+        }
+
+        assert_eq!(tree_encoding_table(&create_huffman_tree(queue).unwrap()), codes);
+    }
+
+    #[test]
+    fn tree_renders_as_indented_branches() {
+        let frequencies = HashMap::from([('a', 3), ('\n', 1), ('c', 1)]);
+        let tree = build_weighted_tree(&frequencies).unwrap();
+
+        let expected = [
+            "* 5",
+            "  0: * 2",
+            "    0: '\\n' 1",
+            "    1: 'c' 1",
+            "  1: 'a' 3",
+        ];
+
+        assert_eq!(tree.to_string().lines().collect::<Vec<_>>(), expected);
+
+        assert_eq!(Node::new_leaf('\t', 7).to_string(), "'\\t' 7\n");
+    }
+
+    #[test]
+    fn table_dump_lists_every_symbol() {
+        let contents = "tab\there, cr\r\nand quotes '\n";
+        let table = generate_encoding_table(contents).unwrap();
+
+        let mut dump = Vec::new();
+        write_table(&table, &mut dump).unwrap();
+
+        let dump = String::from_utf8(dump).unwrap();
+
+        assert_eq!(dump.lines().count(), init_frequency_table(contents).len());
+
+        for (symbol, code) in &table {
+            assert!(dump.lines().any(|line| line == format!("{:?} {} {}", symbol, code.len(), fmt_bitvec(code))));
+        }
+
+        // control characters stay on their line
+        assert!(dump.contains("'\\t' ") && dump.contains("'\\r' ") && dump.contains("'\\n' "));
+
+        // the output file doesn't change
+        let config = Config { print_table: true, ..Config::default() };
+        let contents = contents.repeat(20);
+
+        let (mut plain, mut printed) = (Vec::new(), Vec::new());
+        compress_with(&Config::default(), contents.as_bytes(), &mut plain).unwrap();
+        compress_with(&config, contents.as_bytes(), &mut printed).unwrap();
+
+        assert!(plain == printed);
+    }
+
+    #[test]
+    fn stats_count_the_bytes_written() {
+        assert_eq!(CompressionStats::new(1000, 250, 3).ratio, 0.25);
+        assert_eq!(CompressionStats::new(0, 30, 0).ratio, 1.0);
+
+        let input = std::env::temp_dir().join("huffman_stats_input.txt");
+        let output = std::env::temp_dir().join("huffman_stats_input.txt.o");
+
+        let contents = "aaaaaaaabbbbccd\n".repeat(100);
+        fs::write(&input, &contents).unwrap();
+
+        // every config writes over the output of the previous one
+        let base = Config { input_file: input.to_string_lossy().into_owned(), force: true, ..Config::default() };
+
+        for config in [base.clone(), Config { stream: true, ..base.clone() }] {
+            let stats = compress_with_stats(&config).unwrap();
+            let written = fs::metadata(&output).unwrap().len();
+
+            assert_eq!(stats.original_bytes, contents.len() as u64);
+            assert_eq!(stats.compressed_bytes, written);
+            assert_eq!(stats.ratio, written as f64 / contents.len() as f64);
+            assert_eq!(stats.distinct_symbols, 5);
+        }
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn empty_file_round_trip() {
+        let input = std::env::temp_dir().join("huffman_empty_input.txt");
+        let output = std::env::temp_dir().join("huffman_empty_input.txt.o");
+
+        fs::write(&input, b"").unwrap();
+
+        // every config writes over the output of the previous one
+        let base = Config { input_file: input.to_string_lossy().into_owned(), force: true, ..Config::default() };
+
+        let configs = [
+            Config { ..base.clone() },
+            Config { stream: true, ..base.clone() },
+            Config { best: true, ..base.clone() },
+            Config { symbols: Some(SymbolMode::Binary), ..base.clone() },
+            Config { algorithm: Algorithm::ShannonFano, ..base.clone() },
+        ];
+
+        for config in configs {
+            compress(&config).unwrap();
+
+            let compressed = fs::read(&output).unwrap();
+
+            let mut decoded = Vec::new();
+            crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+            assert!(decoded.is_empty());
+        }
+
+        // the streamed file is coded: no entries and no payload
+        let mut streamed = Vec::new();
+        compress_seekable(&base, io::Cursor::new(b""), &mut streamed).unwrap();
+        assert!(streamed.windows(b"0\n\0\0\0\0\0\0\0\0\0".len()).any(|window| window == b"0\n\0\0\0\0\0\0\0\0\0"));
+
+        // an empty model is written, and read back, without symbols
+        compress(&Config { export_model: true, ..base.clone() }).unwrap();
+        assert!(crate::decode::read_model(&output).unwrap().is_empty());
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn backslashes_and_crlf_round_trip() {
+        let contents = "C:\\Users\\bird\\nest.txt\r\n\\n is not a newline\r\n\\\\\r\r\n\x0b\x0c\x1b[0m\r\n".repeat(30);
+
+        for config in [Config::default(), Config { remap: true, ..Config::default() }] {
+            let mut compressed = Vec::new();
+            compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+            // coded, with every control character and the backslash in the table
+            let table = build_table(&config, &init_frequency_table(&contents)).unwrap();
+            assert!(table.contains_key(&'\\') && table.contains_key(&'\r') && table.contains_key(&'\x1b'));
+            assert!(!compressed.windows(STORE_TAG.len()).any(|window| window == STORE_TAG));
+
+            let mut decoded = Vec::new();
+            crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+            assert_eq!(decoded, contents.as_bytes());
+        }
+    }
+
+    #[test]
+    fn single_symbol_round_trip() {
+        let table = generate_encoding_table("aaaaaa").unwrap();
+
+        assert_eq!(table, HashMap::from([('a', bitvec![u8, Msb0; 0])]));
+
+        for algorithm in [Algorithm::Huffman, Algorithm::ShannonFano] {
+            let config = Config { algorithm, ..Config::default() };
+
+            for contents in ["aaaaaa".to_string(), "a".repeat(1000)] {
+                let mut compressed = Vec::new();
+                compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+                // long enough to be coded with one bit per symbol instead of stored
+                if contents.len() > 6 {
+                    assert!(!compressed.windows(STORE_TAG.len()).any(|window| window == STORE_TAG));
+                }
+
+                let mut decoded = Vec::new();
+                crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+                assert_eq!(decoded, contents.as_bytes());
             }
         }
     }
-}
 
-/// `BinaryHeap` implementation depends on `Ord` and `PartialOrd` traits
-/// for managing how a value is pushed or popped from the internal data structure
-/// this implementation flips the order effectively changing the `BinaryHeap`
-/// collection from a **max heap** (the default) to a **min heap** (priority queue)
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self {
-            Node::Branch(node) => match other {
-                Node::Branch(other_node) => other_node.frequency.cmp(&node.frequency),
-                Node::Leaf(other_node) => other_node.frequency.cmp(&node.frequency),
-            },
-            Node::Leaf(node) => match other {
-                Node::Branch(other_node) => other_node.frequency.cmp(&node.frequency),
-                Node::Leaf(other_node) => other_node.frequency.cmp(&node.frequency),
-            },
+    #[test]
+    fn canonical_codes_round_trip() {
+        let contents = "canonical codes from nothing but their lengths, ünïcödé 語\n".repeat(30);
+
+        let table = generate_encoding_table(&contents).unwrap();
+        let canonical = canonicalize(&table);
+
+        // same lengths, so the same compressed size
+        assert_eq!(canonical.lengths(), table.iter().map(|(&symbol, code)| (symbol, code.len())).collect());
+        assert!(canonical.codes.windows(2).all(|pair| (pair[0].1.len(), pair[0].0) < (pair[1].1.len(), pair[1].0)));
+
+        for algorithm in [Algorithm::Huffman, Algorithm::ShannonFano] {
+            let config = Config { algorithm, ..Config::default() };
+            let table = build_table(&config, &init_frequency_table(&contents)).unwrap();
+
+            let mut compressed = Vec::new();
+            compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+            // only the code lengths are stored
+            let header = String::from_utf8_lossy(&compressed);
+            assert!(header.contains(&format!("{}\n{}", table.len(), fmt_code_lengths(&table))));
+
+            let mut decoded = Vec::new();
+            crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+            assert_eq!(decoded, contents.as_bytes());
         }
     }
-}
 
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
+    #[test]
+    fn exported_model_compresses_other_inputs() {
+        let model_path = std::env::temp_dir().join("huffman_exported.model");
 
-pub fn init_symbol_nodes_prio_queue(frequency_table: &HashMap<char, u32>) -> BinaryHeap<Node> {
-    let mut nodes: BinaryHeap<Node> = BinaryHeap::new();
+        let training = "the model is trained on this sentence\n".repeat(30);
 
-    for (&c, &freq) in frequency_table.iter() {
-        nodes.push(Node::new_leaf(c, freq));
-    }
+        let export = Config { export_model: true, ..Config::default() };
+        let mut model = Vec::new();
+        compress_with(&export, training.as_bytes(), &mut model).unwrap();
 
-    nodes
-}
+        // the table alone, no payload
+        assert!(model.len() < training.len() / 4);
+        fs::write(&model_path, &model).unwrap();
+
+        let err = crate::decode::decompress_from_reader(&mut &model[..], &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("model"));
+
+        let config = Config {
+            model: Some(model_path.to_string_lossy().into_owned()),
+            ..Config::default()
+        };
 
-pub fn create_huffman_tree(mut prio_queue: BinaryHeap<Node>) -> Node {
-    if prio_queue.len() == 0 {
-        panic!("Empty priority queue..aborting");
+        let contents = "the latest tests in the same order\n".repeat(50);
+
+        let mut compressed = Vec::new();
+        compress_with(&config, contents.as_bytes(), &mut compressed).unwrap();
+
+        // the header holds the model's code lengths
+        let lengths = crate::decode::read_model(&model_path).unwrap();
+        let header = String::from_utf8_lossy(&compressed);
+        assert!(header.contains(&fmt_code_lengths(&CanonicalTable::from_lengths(&lengths).into_table())));
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        // symbols the model doesn't have can't be encoded
+        assert!(compress_with(&config, "xyz".repeat(200).as_bytes(), &mut Vec::new()).is_err());
+
+        fs::remove_file(&model_path).unwrap();
     }
 
-    while prio_queue.len() > 1 {
-        if let (Some(n1), Some(n2)) = (prio_queue.pop(), prio_queue.pop()) {
-            // new branch frequency
-            let new_freq: u32 = n1.variant_freq() + n2.variant_freq();
+    #[test]
+    fn dictionary_coded_files_need_their_dictionary() {
+        let dictionary = std::env::temp_dir().join("huffman_dictionary.model");
+        let other = std::env::temp_dir().join("huffman_other_dictionary.model");
 
-            let (left, right) = n1.cmp_pair(n2);
+        let export = Config { export_model: true, ..Config::default() };
 
-            // push the new node back into the priority queue
-            prio_queue.push(
-                Node::Branch(
-                    Root::new(new_freq, left, right)
-                )
-            );
+        for (path, sample) in [(&dictionary, "many small files share this table\n"), (&other, "another table\n")] {
+            let mut model = Vec::new();
+            compress_with(&export, sample.repeat(30).as_bytes(), &mut model).unwrap();
+            fs::write(path, model).unwrap();
+        }
+
+        let with = |path: &Path| Config { dictionary: Some(path.to_string_lossy().into_owned()), ..Config::default() };
+
+        // small enough to be stored without the dictionary
+        let contents = "small files share this table\n".repeat(2);
+        let contents = contents.as_str();
+
+        let mut compressed = Vec::new();
+        compress_with(&with(&dictionary), contents.as_bytes(), &mut compressed).unwrap();
+
+        assert!(compressed.windows(DICTIONARY_TAG.len()).any(|window| window == DICTIONARY_TAG));
+        assert!(compressed.len() < encode_bytes(contents.as_bytes()).len());
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_with(&with(&dictionary), &mut &compressed[..], &mut decoded).unwrap();
+        assert_eq!(decoded, contents.as_bytes());
+
+        for config in [Config::default(), with(&other)] {
+            let err = crate::decode::decompress_with(&config, &mut &compressed[..], &mut Vec::new()).unwrap_err();
+            assert!(err.to_string().contains("dictionary"), "{}", err);
         }
+
+        fs::remove_file(&dictionary).unwrap();
+        fs::remove_file(&other).unwrap();
     }
 
-    // at this point prio_queue will be dropped
-    // since this function takes ownership of the queue
-    // and will be cleaned automatically as it goes out of scope.
-    prio_queue.pop().unwrap()
-}
+    #[test]
+    fn input_the_dictionary_cannot_code_is_coded_on_its_own() {
+        let dictionary = std::env::temp_dir().join("huffman_fallback_dictionary.model");
 
-pub fn generate_encoding_table(contents: &str) -> HashMap<char, BitVec<u8, Msb0>>{
-    let frequency_table = init_frequency_table(&contents);
+        let mut model = Vec::new();
+        compress_with(&Config { export_model: true, ..Config::default() }, b"abcabc", &mut model).unwrap();
+        fs::write(&dictionary, model).unwrap();
 
-    let path = bitvec!(u8, Msb0;);
+        let config = Config { dictionary: Some(dictionary.to_string_lossy().into_owned()), ..Config::default() };
 
-    let mut encoding_table = HashMap::new();
+        // symbols missing from the dictionary, and input that isn't UTF-8
+        let text = "abc xyz ".repeat(40);
+        let binary: Vec<u8> = (0..=255).cycle().take(2000).collect();
 
-    let prio_queue = init_symbol_nodes_prio_queue(&frequency_table);
+        for input in [text.as_bytes(), &binary[..]] {
+            let mut compressed = Vec::new();
+            compress_with(&config, input, &mut compressed).unwrap();
 
-    let tree = create_huffman_tree(prio_queue);
+            assert!(!compressed.windows(DICTIONARY_TAG.len()).any(|window| window == DICTIONARY_TAG));
 
-    tree.generate_encoding(path, &mut encoding_table);
+            for config in [&config, &Config::default()] {
+                let mut decoded = Vec::new();
+                crate::decode::decompress_with(config, &mut &compressed[..], &mut decoded).unwrap();
+                assert_eq!(decoded, input);
+            }
+        }
 
-    encoding_table
-}
+        fs::remove_file(&dictionary).unwrap();
+    }
 
-// Builds the frequency table for all of the characters of the given
-// contents string slice.
-fn init_frequency_table(contents: &str) -> HashMap<char, u32> {
-    let mut frequency_table = HashMap::new();
-    for sym in contents.chars() {
-        // Initializes table entry if doesn't exist
-        // dereferences the entry to increment it by one for each occurance
-        *frequency_table.entry(sym).or_insert(0) += 1;
+    #[test]
+    fn scan_matches_in_memory_counts() {
+        // multi byte symbols straddle the read buffer boundaries
+        let contents = "streaming ünïcödé 語 🦀 counts\n".repeat(5000);
+
+        let scanned = scan(contents.as_bytes()).unwrap();
+
+        let frequency_table = init_frequency_table(&contents);
+        let total = contents.chars().count() as u64;
+
+        assert_eq!(scanned.total, total);
+        assert_eq!(scanned.freqs.len(), frequency_table.len());
+
+        for (symbol, &frequency) in &frequency_table {
+            assert_eq!(scanned.freqs[symbol], frequency as u64);
+        }
+
+        let in_memory = entropy(frequency_table.values().map(|&frequency| frequency as u64), total);
+
+        assert!((scanned.entropy - in_memory).abs() < 1e-9);
+
+        // a stream cut inside a char is an error
+        let cut = &"🦀".as_bytes()[..2];
+        assert_eq!(scan(cut).unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
 
-    frequency_table
-}
+    #[test]
+    fn byte_counters_agree() {
+        let mut seed: u32 = 0xc0ff_ee00;
+        let bytes: Vec<u8> = (0..100_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                // skewed toward low values, leaving some bytes unused
+                ((seed >> 16) as u8) & ((seed >> 24) as u8)
+            })
+            .collect();
+
+        let array = byte_frequency_table(&bytes);
+        let map = byte_frequency_map(&bytes);
+
+        for byte in 0..=255u8 {
+            assert_eq!(array[byte as usize], map.get(&byte).copied().unwrap_or(0), "byte {}", byte);
+        }
 
-pub fn compress(config: &Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.input_file.clone())?;
+        assert_eq!(array.iter().filter(|&&count| count > 0).count(), map.len());
+    }
 
-    let table = generate_encoding_table(&contents);
+    #[test]
+    fn encoded_len_under_a_trained_model() {
+        let encoder = Encoder::new("aaaabbc");
 
-    let out_path = config.get_output_file()?;
+        // a: 1 bit, b and c: 2 bits
+        assert_eq!(encoder.encoded_len("abcabc"), Ok(2));
+        assert_eq!(encoder.encoded_len("aaaaaaaa"), Ok(1));
+        assert_eq!(encoder.encoded_len(""), Ok(0));
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .open(out_path)?;
+        let err = encoder.encoded_len("abd").unwrap_err();
 
-    // writing header
-    let head_buf = format!("{}\n", table.len())
-        .as_bytes()
-        .to_owned();
+        assert_eq!(err, UnmappedSymbol('d'));
+        assert_eq!(err.to_string(), "symbol 'd' is not in the model");
+    }
 
-    file.write(&head_buf)?;
+    #[test]
+    fn byte_lut_matches_the_table() {
+        // symbols past U+00FF have no byte to be looked up by
+        let encoder = Encoder::new("aaaaaaaabbbbcc\0\u{ff}\u{ff}語");
+        let lut = encoder.to_byte_lut();
+
+        for byte in 0..=u8::MAX {
+            let expected = encoder.table().get(&char::from(byte)).map(|code| {
+                let bits = code.iter().by_vals().fold(String::new(), |bits, bit| bits + if bit { "1" } else { "0" });
+                (u32::from_str_radix(&bits, 2).unwrap(), code.len() as u8)
+            });
+
+            assert_eq!(lut[byte as usize], expected, "byte {:#04x}", byte);
+        }
 
+        assert_eq!(lut.iter().flatten().count(), 5);
 
-    let mut table_buf = Vec::new();
+        // packed codes write the same bits as the table's
+        let mut from_table = BitWriter::new(Vec::new());
+        let mut from_lut = BitWriter::new(Vec::new());
 
-    for (symbol, bits) in &table {
+        for byte in b"abca\0\xffbb" {
+            from_table.write_bits(&encoder.table()[&char::from(*byte)]).unwrap();
 
-        let code = fmt_bitvec(bits);
+            let (bits, len) = lut[*byte as usize].unwrap();
+            from_lut.write_packed(bits, len).unwrap();
+        }
 
-        let line_buf = if *symbol == '\n' {
-            format!("{}{}\n", "\\n", code).as_bytes().to_owned()
-        } else {
-            format!("{}{}\n", symbol, code).as_bytes().to_owned()
-        };
+        assert_eq!(from_lut.finish().unwrap(), from_table.finish().unwrap());
+    }
 
-        table_buf.extend(line_buf);
+    #[test]
+    fn canonical_codes_of_a_trained_model() {
+        let encoder = Encoder::new("aaaaaaaabbbbccd\n");
+
+        let codes: Vec<(char, String)> = encoder
+            .codes_canonical()
+            .map(|(symbol, code)| (symbol, fmt_bitvec(&code)))
+            .collect();
+
+        assert_eq!(codes, [
+            ('a', "0".to_string()),
+            ('b', "10".to_string()),
+            ('c', "110".to_string()),
+            ('\n', "1110".to_string()),
+            ('d', "1111".to_string()),
+        ]);
+
+        for (symbol, code) in &codes {
+            assert_eq!(code.len(), encoder.table()[symbol].len());
+        }
     }
 
-    file.write(&table_buf)?;
+    #[test]
+    fn unmapped_symbol_policies() {
+        let weights_path = std::env::temp_dir().join("huffman_unmapped_weights.json");
+        fs::write(&weights_path, r#"{"a": 50, "b": 20, "?": 1}"#).unwrap();
+
+        // `c` has no weight
+        let contents = "aaaaaaaaaabbbbbc".repeat(40);
+
+        let compress = |unmapped| {
+            let config = Config {
+                weights: Some(weights_path.to_string_lossy().into_owned()),
+                unmapped,
+                ..Config::default()
+            };
 
+            let mut compressed = Vec::new();
+            compress_with(&config, contents.as_bytes(), &mut compressed).map(|()| compressed)
+        };
 
-    let mut bit_vec_buff = bitvec!();
-    let mut vec_buf = vec![];
-    for sym in contents.chars() {
-        match table.get(&sym) {
-            Some(bin) => bit_vec_buff.extend(bin),
-            None => continue
+        let decode = |compressed: Vec<u8>| {
+            let mut decoded = Vec::new();
+            crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+            String::from_utf8(decoded).unwrap()
         };
+
+        let err = compress(UnmappedPolicy::Error).unwrap_err();
+        assert_eq!(err.to_string(), "symbol 'c' is not in the model");
+
+        let skipped = decode(compress(UnmappedPolicy::Skip).unwrap());
+        assert_eq!(skipped, contents.replace('c', ""));
+
+        let substituted = decode(compress(UnmappedPolicy::Substitute('?')).unwrap());
+        assert_eq!(substituted, contents.replace('c', "?"));
+
+        // the substitute needs a code too
+        assert!(compress(UnmappedPolicy::Substitute('!')).is_err());
+
+        assert_eq!("substitute=?".parse(), Ok(UnmappedPolicy::Substitute('?')));
+        assert_eq!("skip".parse(), Ok(UnmappedPolicy::Skip));
+        assert!("substitute=ab".parse::<UnmappedPolicy>().is_err());
+
+        fs::remove_file(&weights_path).unwrap();
     }
 
-    for chunk in bit_vec_buff.chunks(8) {
-        vec_buf.push(chunk.load::<u8>());
+    #[test]
+    fn two_pass_matches_in_memory_output() {
+        let input = std::env::temp_dir().join("huffman_two_pass.txt");
+        let output = std::env::temp_dir().join("huffman_two_pass.txt.o");
+
+        let words = ["huffman", "streams", "ünïcödé", "語", "two", "passes", "\n"];
+        let mut seed: u32 = 0x7e57_0001;
+        let contents: String = (0..60_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                words[(seed >> 16) as usize % words.len()].to_string() + " "
+            })
+            .collect();
+
+        fs::write(&input, &contents).unwrap();
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            stream: true,
+            ..Config::default()
+        };
+
+        compress(&config).unwrap();
+
+        let mut in_memory = Vec::new();
+        compress_with(&config, contents.as_bytes(), &mut in_memory).unwrap();
+
+        let streamed = fs::read(&output).unwrap();
+
+        assert!(streamed == in_memory);
+
+        let mut seekable = Vec::new();
+        compress_seekable(&config, io::Cursor::new(contents.as_bytes()), &mut seekable).unwrap();
+
+        assert!(seekable == in_memory);
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &streamed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, contents.as_bytes());
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
     }
-    file.write_all(&vec_buf)?;
 
-    Ok(())
-}
+    #[test]
+    fn progress_counts_up_to_the_output_size() {
+        let contents = "progress reported while the output grows\n".repeat(8_000);
 
-fn fmt_bitvec(bits: &BitSlice<u8, Msb0>) -> String {
-    let mut code = String::new();
-    for bit in bits.iter().by_vals() {
-        match bit {
-            true => code.push('1'),
-            false => code.push('0')
+        let mut reports = Vec::new();
+        let mut out = Vec::new();
+
+        compress_seekable_with_progress(&Config::default(), io::Cursor::new(contents.as_bytes()), &mut out, |progress| {
+            reports.push(progress);
+        }).unwrap();
+
+        // one report per 64 KiB read and one for the footer
+        assert!(reports.len() > 2);
+
+        for pair in reports.windows(2) {
+            assert!(pair[0].bytes_in <= pair[1].bytes_in);
+            assert!(pair[0].bytes_out <= pair[1].bytes_out);
         }
+
+        assert!(reports.iter().all(|progress| progress.total_in == contents.len() as u64));
+        assert!(reports[0].percent() < 100.0);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.bytes_in, contents.len() as u64);
+        assert_eq!(last.percent(), 100.0);
+        assert_eq!(last.bytes_out, out.len() as u64);
+        assert!(last.ratio() < 1.0);
     }
 
-    code
-}
+    #[test]
+    fn chars_match_str_output() {
+        let compressible = "a wonderful bird is the pelican, ünïcödé 語\n".repeat(40);
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        for contents in [compressible.as_str(), "short", ""] {
+            let chars: Vec<char> = contents.chars().collect();
+
+            for config in [Config::default(), Config { remap: true, ..Config::default() }] {
+                let mut from_str = Vec::new();
+                compress_with(&config, contents.as_bytes(), &mut from_str).unwrap();
+
+                let mut from_chars = Vec::new();
+                compress_chars(&config, &chars, &mut from_chars).unwrap();
+
+                assert!(from_chars == from_str, "{contents:?}");
+            }
+        }
+    }
 
     #[test]
     fn it_generates_frequency_table() {
@@ -303,6 +2875,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::single_match)]
     fn min_heap_impl() {
         let mut priority = BinaryHeap::new();
 
@@ -325,6 +2898,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::single_match)]
     fn it_creates_prio_queue_from_frequency_table() {
         let frequency_table: HashMap<char, u32> = HashMap::from([('a', 3), ('s', 2), ('t', 1)]);
 
@@ -343,7 +2917,7 @@ mod test {
 
         let prio_queue = init_symbol_nodes_prio_queue(&frequency_table);
 
-        let tree = create_huffman_tree(prio_queue);
+        let tree = create_huffman_tree(prio_queue).unwrap();
 
         let max_frequency: u32 = frequency_table.values().sum();
 
@@ -352,6 +2926,93 @@ mod test {
         assert_eq!(*tree.variant_freq(), max_frequency);
     }
 
+    #[test]
+    fn bit_writer_flushes_whole_bytes() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        // `101` then `1100`: one bit short of a byte
+        writer.write_bits(bits![u8, Msb0; 1, 0, 1]).unwrap();
+        writer.write_bits(bits![u8, Msb0; 1, 1, 0, 0]).unwrap();
+        assert!(writer.get_ref().is_empty());
+
+        // `01` completes the byte, its first bit is the lowest
+        writer.write_bits(bits![u8, Msb0; 0, 1]).unwrap();
+        assert_eq!(writer.get_ref(), &[0b0001_1101]);
+        assert_eq!(writer.bits(), 9);
+
+        // the last byte is padded with zeros
+        let (bytes, bits) = writer.finish().unwrap();
+        assert_eq!(bytes, [0b0001_1101, 0b0000_0001]);
+        assert_eq!(bits, 9);
+
+        // the decoder reads the bytes back as `Lsb0` and gets the codes in order
+        let mut unpacked = BitVec::<u8, Lsb0>::from_vec(bytes);
+        unpacked.truncate(bits as usize);
+        assert_eq!(unpacked, bits![1, 0, 1, 1, 1, 0, 0, 0, 1]);
+
+        // a whole number of bytes needs no padding byte
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bits(&bitvec![u8, Msb0; 1; 16]).unwrap();
+        assert_eq!(writer.finish().unwrap(), (vec![0xff, 0xff], 16));
+
+        assert_eq!(BitWriter::new(Vec::new()).finish().unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn slice_encoding_matches_payload() {
+        let contents = "slices have no room to grow\n".repeat(8);
+        let model = Encoder::new(&contents);
+
+        let expected = encode_payload(contents.chars(), model.table(), UnmappedPolicy::Error, Instant::now(), None).unwrap().bytes;
+
+        // exact fit, over a dirty buffer
+        let mut exact = vec![0xff; expected.len()];
+        assert_eq!(encode_into_slice(&model, &contents, &mut exact), Ok(expected.len()));
+        assert_eq!(exact, expected);
+
+        // room to spare is left untouched
+        let mut roomy = vec![0xaa; expected.len() + 4];
+        assert_eq!(encode_into_slice(&model, &contents, &mut roomy), Ok(expected.len()));
+        assert_eq!(roomy[..expected.len()], expected[..]);
+        assert!(roomy[expected.len()..].iter().all(|&byte| byte == 0xaa));
+    }
+
+    #[test]
+    fn slice_encoding_reports_overflow() {
+        let contents = "slices have no room to grow\n".repeat(8);
+        let model = Encoder::new(&contents);
+        let needed = model.encoded_len(&contents).unwrap();
+
+        let mut short = vec![0; needed - 1];
+        assert_eq!(
+            encode_into_slice(&model, &contents, &mut short),
+            Err(SliceEncodeError::BufferTooSmall(BufferTooSmall { needed, capacity: needed - 1 }))
+        );
+
+        assert_eq!(
+            encode_into_slice(&model, &contents, &mut []),
+            Err(SliceEncodeError::BufferTooSmall(BufferTooSmall { needed, capacity: 0 }))
+        );
+        assert_eq!(encode_into_slice(&model, "", &mut []), Ok(0));
+    }
+
+    #[test]
+    fn slice_encoding_reports_unmapped_symbols() {
+        let model = Encoder::new("aaaabbc");
+        let mut out = [0; 16];
+
+        assert_eq!(encode_into_slice(&model, "abd", &mut out), Err(SliceEncodeError::Unmapped(UnmappedSymbol('d'))));
+        assert_eq!(encode_into_slice(&model, "abcd", &mut []), Err(SliceEncodeError::Unmapped(UnmappedSymbol('d'))));
+    }
+
+    #[test]
+    fn empty_queue_is_an_error() {
+        let err = create_huffman_tree(BinaryHeap::new()).unwrap_err();
+
+        assert!(matches!(err, HuffmanError::Internal(_)));
+        assert!(build_weighted_tree(&HashMap::new()).is_err());
+    }
+
     #[test]
     fn it_sorts_node_pair() {
         let mut r1 = Root::default();
@@ -373,10 +3034,260 @@ mod test {
     fn it_generates_correct_encoding() {
         let txt = "dddddbbbaae";
         
-        let encoding_table = generate_encoding_table(txt);
+        let encoding_table = generate_encoding_table(txt).unwrap();
 
         let code = encoding_table.get(&'d').unwrap();
 
         assert_eq!(code, &bitvec![u8, Msb0; 0]);
     }
+
+    /// Records every `write` call so the number of flushed chunks can be checked.
+    #[derive(Default)]
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shown_tree_lists_every_symbol() {
+        let tree = build_huffman_tree("aaabbc").unwrap();
+
+        let mut out = Vec::new();
+        write_tree(&tree, &mut out).unwrap();
+
+        let shown = String::from_utf8(out).unwrap();
+
+        for symbol in ["'a'", "'b'", "'c'"] {
+            assert!(shown.contains(symbol), "{} missing from:\n{}", symbol, shown);
+        }
+    }
+
+    #[test]
+    fn skewed_text_is_worth_compressing() {
+        let txt = "a".repeat(900) + &"b".repeat(90) + "cdefghij";
+
+        assert!(should_compress(&txt));
+    }
+
+    #[test]
+    fn random_text_is_not_worth_compressing() {
+        let mut seed: u32 = 0x9e37_79b9;
+        let txt: String = (0..1000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                char::from((seed >> 16) as u8 & 0x7f)
+            })
+            .collect();
+
+        assert!(!should_compress(&txt));
+        assert!(!should_compress(""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_output_names_the_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let input = std::env::temp_dir().join("huffman_read_only_input.txt");
+        let output = std::env::temp_dir().join("huffman_read_only_output.o");
+
+        fs::write(&input, "read only").unwrap();
+        fs::write(&output, "").unwrap();
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o444)).unwrap();
+
+        // privileged users (root) ignore the permission bits
+        if fs::OpenOptions::new().write(true).open(&output).is_ok() {
+            fs::remove_file(&input).unwrap();
+            fs::remove_file(&output).unwrap();
+            return;
+        }
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            ..Config::default()
+        };
+
+        let err = compress(&config).unwrap_err();
+        let err = err.downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(err.to_string().contains(&*output.to_string_lossy()));
+        assert!(err.to_string().contains("permission"));
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn auto_rename_numbers_existing_outputs() {
+        let dir = std::env::temp_dir().join("huffman_auto_rename");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let input = dir.join("input.txt");
+        fs::write(&input, "renamed rather than overwritten").unwrap();
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            auto_rename: true,
+            ..Config::default()
+        };
+
+        compress(&config).unwrap();
+        compress(&config).unwrap();
+
+        assert!(dir.join("input.txt.o").exists());
+        assert!(dir.join("input.txt.1.o").exists());
+        assert!(!dir.join("input.txt.2.o").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn time_limit_aborts_without_output() {
+        let input = std::env::temp_dir().join("huffman_time_limit_input.txt");
+        let output = std::env::temp_dir().join("huffman_time_limit_output.o");
+
+        fs::write(&input, "abracadabra".repeat(100_000)).unwrap();
+        let _ = fs::remove_file(&output);
+
+        let config = Config {
+            input_file: input.to_string_lossy().into_owned(),
+            output: OutputTarget::File(output.clone()),
+            time_limit: Some(Duration::ZERO),
+            ..Config::default()
+        };
+
+        let err = compress(&config).unwrap_err();
+        let err = err.downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(!output.exists());
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn time_limit_aborts_streaming_compression() {
+        let config = Config { time_limit: Some(Duration::ZERO), ..Config::default() };
+        let input = "abracadabra".repeat(100_000);
+
+        let err = compress_seekable(&config, io::Cursor::new(input), &mut Vec::new()).unwrap_err();
+        let err = err.downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn detects_text_and_binary_files() {
+        let text = std::env::temp_dir().join("huffman_detect_text.txt");
+        let binary = std::env::temp_dir().join("huffman_detect_binary.bin");
+
+        fs::write(&text, "plain text,\twith tabs and ünïcödé 語\r\n".repeat(400)).unwrap();
+
+        let mut seed: u32 = 0xb1a_7e57;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+
+        fs::write(&binary, &noise).unwrap();
+
+        assert_eq!(detect_mode(&text).unwrap(), SymbolMode::Text);
+        assert_eq!(detect_mode(&binary).unwrap(), SymbolMode::Binary);
+
+        // a character cut off by the sample doesn't make it binary
+        let cut = "語".repeat(DETECT_SAMPLE);
+        assert_eq!(symbol_mode(&cut.as_bytes()[..DETECT_SAMPLE], true), SymbolMode::Text);
+
+        // but one at the end of the whole input does
+        assert_eq!(symbol_mode(b"hello world \xe2\x82", false), SymbolMode::Binary);
+        assert_eq!(input_symbol_mode(&Config::default(), b"hello world \xe2\x82"), SymbolMode::Binary);
+
+        assert_eq!(symbol_mode(b"valid UTF-8\0\0\0\x01\x02", false), SymbolMode::Binary);
+
+        // detected binary input doesn't fail as invalid UTF-8, noise like this is stored
+        compress(&Config {
+            input_file: binary.to_string_lossy().into_owned(),
+            ..Config::default()
+        }).unwrap();
+
+        let archive = std::env::temp_dir().join("huffman_detect_binary.bin.o");
+        let compressed = fs::read(&archive).unwrap();
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, noise);
+
+        for path in [&text, &binary, &archive] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn binary_input_is_coded_by_byte() {
+        // nulls, high bytes and invalid UTF-8, skewed so that coding pays off
+        let blob: Vec<u8> = [&[0x00, 0x00, 0x00, 0xff, 0x00, 0x80, 0xfe, 0x00][..], b"\xc3\x28\x00\x00"]
+            .concat()
+            .repeat(64);
+
+        assert_eq!(symbol_mode(&blob, false), SymbolMode::Binary);
+
+        let config = Config { symbols: Some(SymbolMode::Binary), ..Config::default() };
+
+        let mut compressed = Vec::new();
+        compress_with(&config, &blob, &mut compressed).unwrap();
+
+        assert!(compressed[MAGIC.len() + 1..].starts_with(b"0:0:bytes\n"));
+        assert!(compressed.len() < blob.len() / 2);
+
+        let table = byte_encoding_table(&config, &byte_frequency_table(&blob)).unwrap();
+        assert_eq!(table.len(), 6);
+        assert_eq!(table[&0x00].len(), 1);
+
+        let mut decoded = Vec::new();
+        crate::decode::decompress_from_reader(&mut &compressed[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, blob);
+
+        // and through the in-memory API, which detects the mode itself
+        assert_eq!(crate::decode::decode_bytes(&encode_bytes(&blob)).unwrap(), blob);
+
+        // text up to a character cut off at the very end
+        let cut = b"hello world \xe2\x82";
+        assert_eq!(crate::decode::decode_bytes(&encode_bytes(cut)).unwrap(), cut);
+    }
+
+    #[test]
+    fn chunk_size_does_not_change_output() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let mut small = CountingWriter::default();
+        let mut large = CountingWriter::default();
+
+        write_chunked(&mut small, &payload, 7).unwrap();
+        write_chunked(&mut large, &payload, crate::DEFAULT_WRITE_CHUNK_SIZE).unwrap();
+
+        assert_eq!(small.bytes, payload);
+        assert_eq!(large.bytes, payload);
+
+        assert_eq!(small.writes, 143);
+        assert_eq!(large.writes, 1);
+    }
 }
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::io;
+
+/// Errors reported by the library for malformed or out of bounds data.
+#[derive(Debug)]
+pub enum HuffmanError {
+    /// Reading or writing the underlying data failed.
+    Io(io::Error),
+    /// The data doesn't start with the format's magic bytes.
+    NotHuffmanFile,
+    /// The data was written in a format version this build can't read.
+    UnsupportedVersion(u8),
+    /// A serialized tree doesn't describe a valid huffman tree.
+    InvalidTree(&'static str),
+    /// Decoding would produce more than `limit` bytes of output.
+    OutputLimitExceeded { limit: usize },
+    /// An internal invariant broke, e.g. building a tree from no symbols.
+    Internal(&'static str),
+    /// The table count line declares a different number of entries
+    /// than the table holds or its tree has leaves.
+    EntryCountMismatch { declared: usize, found: usize },
+    /// The table count line isn't a number.
+    InvalidHeaderCount(String),
+    /// Fewer payload bytes follow the header than it declares.
+    TruncatedPayload { declared: u64, available: usize },
+    /// The padding bit count doesn't fit in the last payload byte.
+    InvalidPadding { padding: u8, payload_len: u64 },
+    /// The table declares no symbols but a payload follows.
+    PayloadWithoutSymbols { payload_len: u64 },
+    /// The payload has more bits than the original length could be coded in.
+    OriginalLenMismatch { original_len: u64, payload_bits: u64 },
+    /// The CRC-32 of the decoded output differs from the one stored at compression.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The payload bit at `bit_offset` leads to a branch the tree doesn't have.
+    InvalidCodePath { bit_offset: usize },
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HuffmanError::Io(err) => write!(f, "{}", err),
+            HuffmanError::NotHuffmanFile => write!(f, "input is not a huffman file; did you mean to compress?"),
+            HuffmanError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {}, this build reads version {}",
+                version,
+                crate::FORMAT_VERSION
+            ),
+            HuffmanError::InvalidTree(reason) => write!(f, "invalid serialized tree: {}", reason),
+            HuffmanError::OutputLimitExceeded { limit } => {
+                write!(f, "decoded output exceeds the limit of {} bytes", limit)
+            },
+            HuffmanError::Internal(reason) => write!(f, "internal error: {}", reason),
+            HuffmanError::EntryCountMismatch { declared, found } => {
+                write!(f, "header declares {} table entries but {} were found", declared, found)
+            },
+            HuffmanError::InvalidHeaderCount(line) => write!(f, "expected the table entry count, found `{}`", line),
+            HuffmanError::TruncatedPayload { declared, available } => {
+                write!(f, "header declares {} payload bytes but only {} follow", declared, available)
+            },
+            HuffmanError::InvalidPadding { padding, payload_len } => {
+                write!(f, "{} padding bits in the last of {} payload bytes", padding, payload_len)
+            },
+            HuffmanError::PayloadWithoutSymbols { payload_len } => write!(
+                f,
+                "empty table but nonempty payload: header declares no symbols but {} payload bytes follow",
+                payload_len
+            ),
+            HuffmanError::OriginalLenMismatch { original_len, payload_bits } => {
+                write!(f, "{} payload bits can't code an original of {} bytes", payload_bits, original_len)
+            },
+            HuffmanError::InvalidCodePath { bit_offset } => {
+                write!(f, "payload bit {} walks off the huffman tree", bit_offset)
+            },
+            HuffmanError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {:08x} but the decoded output has {:08x}, the file is corrupted",
+                expected,
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HuffmanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HuffmanError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Errors from decoding compressed data in memory with `decode_bytes`.
+pub type DecodeError = HuffmanError;
+
+impl From<io::Error> for HuffmanError {
+    fn from(err: io::Error) -> Self {
+        // format errors that went through `io::Error` come back as themselves
+        match err.get_ref().is_some_and(|inner| inner.is::<HuffmanError>()) {
+            true => *err.into_inner().unwrap().downcast::<HuffmanError>().unwrap(),
+            false => HuffmanError::Io(err),
+        }
+    }
+}
+
+/// Lets the io based `compress`/`decompress` paths propagate format errors with `?`.
+impl From<HuffmanError> for io::Error {
+    fn from(err: HuffmanError) -> Self {
+        match err {
+            HuffmanError::Io(err) => err,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
+
+/// Errors from parsing the command line arguments with `Config::from_iter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The arguments didn't start with the program name.
+    MissingProgramName,
+    /// `-o` was the last argument.
+    MissingOutputName,
+    /// `-o` was followed by the given flag instead of a file name.
+    UnexpectedFlagAfterOutput(String),
+    /// No input file was given for an option that can't read stdin.
+    MissingInputFile,
+    /// Any other invalid argument or combination of arguments.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingProgramName => write!(f, "Program name was not included in arguments list."),
+            ConfigError::MissingOutputName => write!(f, "Expected an output file name after '-o' flag."),
+            ConfigError::UnexpectedFlagAfterOutput(flag) => {
+                write!(f, "Expected filename argument got the flag '{}' instead.", flag)
+            },
+            ConfigError::MissingInputFile => write!(f, "Expected an input file name, use '--' before one starting with '-'."),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<&'static str> for ConfigError {
+    fn from(reason: &'static str) -> Self {
+        ConfigError::Invalid(reason)
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Size of the fixed part of the footer (`name_len`, `original_len`, `checksum`).
+pub const FOOTER_FIXED_SIZE: u64 = 2 + 8 + 4;
+
+/// Metadata written after the payload so it can be computed while encoding.
+///
+/// Layout (integers are little endian):
+/// ```text
+/// name (name_len bytes, UTF-8) | name_len: u16 | original_len: u64 | checksum: u32
+/// ```
+/// The fixed part sits at the very end of the file, so a reader seeks to
+/// `FOOTER_FIXED_SIZE` bytes before the end and then back over the name.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Footer {
+    /// File name of the original input (final path component only).
+    pub name: String,
+    /// Length in bytes of the original input.
+    pub original_len: u64,
+    /// CRC-32 of the original input.
+    pub checksum: u32,
+}
+
+impl Footer {
+    /// Number of bytes the footer takes up at the end of the file.
+    pub fn encoded_len(&self) -> u64 {
+        self.name.len() as u64 + FOOTER_FIXED_SIZE
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        let name_len: u16 = self.name.len().try_into().map_err(|_| {
+            Error::new(ErrorKind::InvalidInput, "file name is too long to store in the footer")
+        })?;
+
+        out.write_all(self.name.as_bytes())?;
+        out.write_all(&name_len.to_le_bytes())?;
+        out.write_all(&self.original_len.to_le_bytes())?;
+        out.write_all(&self.checksum.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    /// Reads the footer by seeking from the end of `reader`,
+    /// the reader's position is left at the end of the file.
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Footer, Error> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+
+        if file_len < FOOTER_FIXED_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "file is too short to hold a footer"));
+        }
+
+        let mut fixed = [0u8; FOOTER_FIXED_SIZE as usize];
+        reader.seek(SeekFrom::End(-(FOOTER_FIXED_SIZE as i64)))?;
+        reader.read_exact(&mut fixed)?;
+
+        // fixed size slices of a fixed size array, unwrapping can't fail.
+        let name_len = u16::from_le_bytes(fixed[0..2].try_into().unwrap()) as u64;
+        let original_len = u64::from_le_bytes(fixed[2..10].try_into().unwrap());
+        let checksum = u32::from_le_bytes(fixed[10..14].try_into().unwrap());
+
+        if file_len < FOOTER_FIXED_SIZE + name_len {
+            return Err(Error::new(ErrorKind::InvalidData, "footer name runs past the start of the file"));
+        }
+
+        let mut name = vec![0u8; name_len as usize];
+        reader.seek(SeekFrom::End(-((FOOTER_FIXED_SIZE + name_len) as i64)))?;
+        reader.read_exact(&mut name)?;
+
+        let name = String::from_utf8(name)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "footer name is not valid UTF-8"))?;
+
+        reader.seek(SeekFrom::End(0))?;
+
+        Ok(Footer {
+            name,
+            original_len,
+            checksum
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn footer_round_trip() {
+        let footer = Footer {
+            name: "bird".to_string(),
+            original_len: 1635,
+            checksum: 0xdead_beef,
+        };
+
+        let mut buf = b"payload".to_vec();
+        footer.write_to(&mut buf).unwrap();
+
+        assert_eq!(buf.len() as u64, 7 + footer.encoded_len());
+
+        let read = Footer::read_from(&mut Cursor::new(buf)).unwrap();
+
+        assert_eq!(read, footer);
+    }
+
+    #[test]
+    fn short_file_has_no_footer() {
+        let err = Footer::read_from(&mut Cursor::new(b"0\n".to_vec())).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
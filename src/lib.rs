@@ -1,28 +1,204 @@
 use std::error::Error;
-use std::io::Error as IoError;
-use std::fs::File;
-use std::path::PathBuf;
+use std::io::{BufRead, Error as IoError, ErrorKind, Write};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub mod encode;
 pub mod decode;
+pub mod footer;
+pub mod charset;
+pub mod error;
+pub mod transform;
+pub mod remap;
+pub mod selftest;
+pub mod base64;
+pub mod archive;
+pub mod codec;
+#[cfg(feature = "deflate")]
+pub mod deflate;
 
+pub use error::{ConfigError, DecodeError, HuffmanError};
+
+/// Default number of payload bytes buffered before each write to the output.
+pub const DEFAULT_WRITE_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Bytes every compressed file starts with, followed by the format version byte.
+pub const MAGIC: &[u8; 3] = b"HUF";
+
+/// Version of the compressed format written by this build.
+/// - 1: magic and version bytes
+/// - 2: length prefixed comment after the version byte
+/// - 3: 8 byte little endian payload length before the payload
+/// - 4: length prefixed list of transform ids before the huffman header
+/// - 5: symbol mapping after the transform ids
+/// - 6: length prefixed owner (uid and gid) after the comment
+/// - 7: code building algorithm byte before the transform ids
+/// - 8: payload bit order byte after the algorithm byte
+/// - 9: number of padding bits in the last payload byte after the payload length
+/// - 10: symbol frequencies instead of codes in the table, the decoder rebuilds the codes
+/// - 11: code lengths instead of frequencies in the table, the codes are canonical
+/// - 12: `BYTES_TAG` body, the table and payload code bytes instead of chars
+/// - 13: `BLOCKS_TAG` body, a block count and each block as a length prefixed compressed file
+/// - 14: `DICTIONARY_TAG` body, the CRC-32 of the model file and the payload, without a table
+pub const FORMAT_VERSION: u8 = 14;
+
+/// First header line of a file whose input was stored verbatim
+/// because huffman coding wouldn't have made it any smaller.
+pub const STORE_TAG: &[u8] = b"store\n";
+
+/// First header line of a file whose input was coded byte by byte
+/// because it isn't text.
+pub const BYTES_TAG: &[u8] = b"bytes\n";
+
+/// First header line of a model file, which holds only the code table
+/// of its input and no payload.
+pub const MODEL_TAG: &[u8] = b"model\n";
+
+/// First header line of a file coded with the table of a `--dictionary` model
+/// file, which it doesn't hold and names by the CRC-32 of the model file instead.
+pub const DICTIONARY_TAG: &[u8] = b"dict\n";
+
+/// First header line of a file whose input was split into blocks,
+/// each coded as a compressed file of its own.
+pub const BLOCKS_TAG: &[u8] = b"blocks\n";
+
+/// Where the input is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    File(PathBuf),
+    /// Standard input, for an input file of `-` or none at all.
+    Stdin,
+}
+
+impl InputSource {
+    /// Opens the input for buffered reading, stdin can't seek back
+    /// so everything read from it goes through the buffer.
+    pub fn open(&self) -> Result<Box<dyn BufRead>, IoError> {
+        match self {
+            InputSource::File(path) => Ok(Box::new(std::io::BufReader::new(File::open(path)?))),
+            InputSource::Stdin => Ok(Box::new(std::io::stdin().lock())),
+        }
+    }
+}
+
+/// Where the compressed or decompressed output goes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputTarget {
+    /// A file named after the input: `<input>.o`, or `<input>.sh` with `--self-extract`.
+    #[default]
+    Derived,
+    /// The file given with `-o`.
+    File(PathBuf),
+    /// Standard output, no file is created.
+    Stdout,
+}
+
+#[derive(Clone)]
 pub struct Config {
     pub input_file: String,
-    pub output_file: Option<String>,
+    /// Every input file when more than one is given, compressed into one archive.
+    /// `input_file` is the first of them.
+    pub inputs: Vec<String>,
+    pub output: OutputTarget,
     pub mode: Mode,
+    pub write_chunk_size: usize,
+    pub time_limit: Option<Duration>,
+    /// Text encoding of the uncompressed data, UTF-8 when `None`.
+    pub encoding: Option<String>,
+    /// Print the huffman tree to stderr before encoding.
+    pub show_tree: bool,
+    /// Print the code of every symbol to stderr before encoding.
+    pub print_table: bool,
+    /// Free form comment stored in the header.
+    pub comment: String,
+    /// Pick the next free numbered name instead of overwriting an existing output.
+    pub auto_rename: bool,
+    /// Overwrite an existing output file instead of failing.
+    pub force: bool,
+    /// Replace the input file with the output, keeping its name.
+    pub in_place: bool,
+    /// Ids of the transforms applied to the text before coding, in order.
+    pub transforms: Vec<u8>,
+    /// Largest transform list, symbol count and table in bytes,
+    /// a bigger header stores the input instead.
+    pub max_header_bytes: Option<usize>,
+    /// Split inputs longer than this many bytes into blocks coded with a table each.
+    pub block_size: Option<usize>,
+    /// Code dense indices of the symbols instead of the symbols themselves.
+    pub remap: bool,
+    /// JSON file of symbol weights used as the model instead of counting the input.
+    pub weights: Option<String>,
+    /// Try every strategy and keep the smallest output.
+    pub best: bool,
+    /// Read the input from stdin and write the output to stdout.
+    pub pipe: bool,
+    /// Wrap the compressed output in a shell script that restores the input when run.
+    pub self_extract: bool,
+    /// Store the uid and gid of the input and restore them on decompression.
+    pub preserve_owner: bool,
+    /// Original file the decompressed input is compared with.
+    pub verify_against: Option<String>,
+    /// Handling of input symbols missing from a supplied model.
+    pub unmapped: encode::UnmappedPolicy,
+    /// Compress in two passes over the input file instead of reading it into memory.
+    pub stream: bool,
+    /// How the codes are built from the symbol frequencies.
+    pub algorithm: encode::Algorithm,
+    /// Symbol mode forced by `--text` or `--binary`, otherwise detected from the input file.
+    pub symbols: Option<encode::SymbolMode>,
+    /// Show the bytes read and written and their ratio while compressing with `--stream`.
+    pub progress: bool,
+    /// Skip comparing the checksum of the decompressed output with the stored one.
+    pub no_verify: bool,
+    /// Write only the code table of the input, as a model file for `--model`.
+    pub export_model: bool,
+    /// Model file whose code table is used instead of one built from the input.
+    pub model: Option<String>,
+    /// Model file whose code table is used and left out of the output,
+    /// decompression needs the same file.
+    pub dictionary: Option<String>,
+    /// Don't print the compression summary.
+    pub quiet: bool,
+    /// Decompress inputs starting with the magic bytes, compress everything else.
+    pub auto: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Mode {
     Compress,
-    Decompress
+    Decompress,
+    Inspect,
+    SelfTest,
+    VerifyAgainst,
+    /// Compress and decompress in memory, checking the round trip is lossless.
+    Verify,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = &'static str;
+
+    /// Parses `compress`, `decompress`, `inspect` or `verify`, or their first letters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compress" | "c" => Ok(Mode::Compress),
+            "decompress" | "d" => Ok(Mode::Decompress),
+            "inspect" | "i" => Ok(Mode::Inspect),
+            "verify" | "v" => Ok(Mode::Verify),
+            _ => Err("Expected `compress`, `decompress`, `inspect` or `verify`."),
+        }
+    }
 }
 
 impl std::fmt::Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Mode::Compress => write!(f, "Compression"),
-            Mode::Decompress => write!(f, "Decompression")
+            Mode::Decompress => write!(f, "Decompression"),
+            Mode::Inspect => write!(f, "Inspection"),
+            Mode::SelfTest => write!(f, "Self-test"),
+            Mode::VerifyAgainst => write!(f, "Verification"),
+            Mode::Verify => write!(f, "Round trip verification"),
         }
     }
 }
@@ -31,73 +207,949 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             input_file: String::default(),
-            output_file: None,
+            inputs: Vec::new(),
+            output: OutputTarget::Derived,
             mode: Mode::Compress,
+            write_chunk_size: DEFAULT_WRITE_CHUNK_SIZE,
+            time_limit: None,
+            encoding: None,
+            show_tree: false,
+            print_table: false,
+            comment: String::new(),
+            auto_rename: false,
+            force: false,
+            in_place: false,
+            transforms: Vec::new(),
+            max_header_bytes: None,
+            block_size: None,
+            remap: false,
+            weights: None,
+            best: false,
+            pipe: false,
+            self_extract: false,
+            preserve_owner: false,
+            verify_against: None,
+            unmapped: encode::UnmappedPolicy::Error,
+            stream: false,
+            algorithm: encode::Algorithm::Huffman,
+            symbols: None,
+            progress: false,
+            no_verify: false,
+            export_model: false,
+            model: None,
+            dictionary: None,
+            quiet: false,
+            auto: false,
         }
     }
 }
 
 
+/// Builds a `Config` from library code, with the checks `from_iter` does on arguments:
+/// ```no_run
+/// let config = huffman::Config::builder()
+///     .input_file("bird")
+///     .output_file(Some("bird.huff".to_string()))
+///     .mode(huffman::Mode::Compress)
+///     .build()?;
+/// # Ok::<(), huffman::ConfigError>(())
+/// ```
+/// Options without a method keep their defaults.
+#[derive(Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// File to read, stdin when `-` or not set.
+    pub fn input_file(mut self, input_file: impl Into<String>) -> Self {
+        self.config.input_file = input_file.into();
+        self
+    }
+
+    /// File to write, named after the input file when `None`.
+    pub fn output_file(mut self, output_file: Option<String>) -> Self {
+        self.config.output = match output_file {
+            Some(path) => OutputTarget::File(PathBuf::from(path)),
+            None => OutputTarget::Derived,
+        };
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Checks the combination of options, an input of stdin without
+    /// an output file writes to stdout.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut config = self.config;
+
+        if matches!(config.mode, Mode::Inspect | Mode::VerifyAgainst) && config.input_source() == InputSource::Stdin {
+            return Err(ConfigError::MissingInputFile);
+        }
+
+
+        if config.inputs.len() > 1 {
+            if !matches!(config.mode, Mode::Compress) {
+                return Err("Only compression takes several input files.".into());
+            }
+
+            if config.output == OutputTarget::Derived {
+                return Err("Compressing several files needs '-o' or '--stdout'.".into());
+            }
+
+            if config.in_place || config.pipe || config.self_extract || config.stream || config.export_model {
+                return Err("Compressing several files can't be combined with '--in-place', '--pipe', '--self-extract', '--stream' or '--export-model'.".into());
+            }
+        }
+
+        if config.auto && (config.mode != Mode::Compress || !config.inputs.is_empty()) {
+            return Err("The '--auto' flag picks the mode of a single input, it can't be combined with '-d', '--mode' or several inputs.".into());
+        }
+
+        if config.in_place && config.output != OutputTarget::Derived {
+            return Err("The '--in-place' flag can't be combined with '-o' or '--stdout'.".into());
+        }
+
+        if config.pipe && (!config.input_file.is_empty() || config.output != OutputTarget::Derived || config.in_place) {
+            return Err("The '--pipe' flag can't be combined with file names, '-o', '--stdout' or '--in-place'.".into());
+        }
+
+        if config.self_extract && (config.pipe || config.in_place) {
+            return Err("The '--self-extract' flag can't be combined with '--pipe' or '--in-place'.".into());
+        }
+
+        let plain = !(config.in_place || config.pipe || config.self_extract || config.best || config.remap)
+            && config.weights.is_none()
+            && config.model.is_none()
+            && config.dictionary.is_none()
+            && !config.export_model
+            && config.encoding.is_none()
+            && config.block_size.is_none()
+            && config.transforms.is_empty()
+            && config.symbols != Some(encode::SymbolMode::Binary);
+
+        if config.stream && !plain {
+            return Err("The '--stream' flag only supports plain huffman coding of a UTF-8 file.".into());
+        }
+
+        if config.block_size.is_some() && (config.export_model || config.encoding.is_some()) {
+            return Err("The '--block-size' flag can't be combined with '--export-model' or '--encoding'.".into());
+        }
+
+        if config.show_tree && config.algorithm != encode::Algorithm::Huffman {
+            return Err("The '--show-tree' flag only shows huffman trees.".into());
+        }
+
+        if config.progress && !config.stream {
+            return Err("The '--progress' flag needs '--stream'.".into());
+        }
+
+        if config.remap && config.weights.is_some() {
+            return Err("The '--remap' flag can't be combined with '--weights'.".into());
+        }
+
+        if config.export_model && (config.in_place || config.pipe || config.self_extract || config.best || config.remap) {
+            return Err("The '--export-model' flag can't be combined with '--in-place', '--pipe', '--self-extract', '--best' or '--remap'.".into());
+        }
+
+        if config.dictionary.is_some()
+            && (config.model.is_some() || config.weights.is_some() || config.export_model || config.best || config.remap
+                || !config.transforms.is_empty() || config.block_size.is_some())
+        {
+            return Err("The '--dictionary' flag can't be combined with '--model', '--weights', '--export-model', '--train', '--best', '--remap', '--transform' or '--block-size'.".into());
+        }
+
+        if config.model.is_some() && (config.export_model || config.best || config.remap || config.weights.is_some()) {
+            return Err("The '--model' flag can't be combined with '--export-model', '--best', '--remap' or '--weights'.".into());
+        }
+
+        if !config.pipe && config.input_source() == InputSource::Stdin {
+            if config.input_file.is_empty() && (config.in_place || config.stream || config.preserve_owner) {
+                return Err(ConfigError::MissingInputFile);
+            }
+
+            if config.in_place || config.stream || config.preserve_owner {
+                return Err("Reading stdin can't be combined with '--in-place', '--stream' or '--preserve-owner'.".into());
+            }
+
+            // without a name to derive one from the output goes to stdout
+            if config.output == OutputTarget::Derived {
+                config.output = OutputTarget::Stdout;
+            }
+        }
+
+        Ok(config)
+    }
+}
+
 impl Config {
+    /// Builder for configuring from library code instead of arguments.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
     /// Parse Config from args iterator
-    /// # Panics
-    /// - Empty args iterator
-    /// - No output file name provided after '-o' flag (next is a flag or next is empty)
-    pub fn from_iter(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next()
-            .expect("Program name was not included in arguments list.");
+    /// # Errors
+    /// - `ConfigError::MissingProgramName` if the args iterator is empty
+    /// - `ConfigError::MissingOutputName` if '-o' is the last argument
+    /// - `ConfigError::UnexpectedFlagAfterOutput` if '-o' is followed by a flag
+    /// - `ConfigError::MissingInputFile` if no input file is given for an option that can't read stdin
+    /// - `ConfigError::Invalid` for any other invalid argument or combination of arguments
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        args.next().ok_or(ConfigError::MissingProgramName)?;
 
         let mut config = Config::default();
 
         while let Some(arg) = args.next() {
             match arg.as_str() {
                 "-d" => config.mode = Mode::Decompress,
+                "--show-tree" => config.show_tree = true,
+                "--print-table" => config.print_table = true,
+                "--inspect" => config.mode = Mode::Inspect,
+                "--verify" => config.mode = Mode::Verify,
+                "--self-test" => config.mode = Mode::SelfTest,
+                "--auto-rename" => config.auto_rename = true,
+                "--force" => config.force = true,
+                "--in-place" => config.in_place = true,
+                "--remap" => config.remap = true,
+                "--best" => config.best = true,
+                "--pipe" => config.pipe = true,
+                "--stream" => config.stream = true,
+                "--progress" => config.progress = true,
+                "--no-verify" => config.no_verify = true,
+                "--export-model" => config.export_model = true,
+                "--quiet" => config.quiet = true,
+                "--auto" => config.auto = true,
+                "--stdout" => config.output = OutputTarget::Stdout,
+                "--text" => config.symbols = Some(encode::SymbolMode::Text),
+                "--binary" => config.symbols = Some(encode::SymbolMode::Binary),
+                "--self-extract" => config.self_extract = true,
+                "--preserve-owner" => config.preserve_owner = true,
+                "--comment" => {
+                    config.comment = args.next().ok_or("Expected a comment after '--comment' flag.")?;
+                },
                 "-o" => {
-                    config.output_file = match args.next() {
-                        Some(param) => {
-                            if param.starts_with('-') {
-                                panic!("Expected filename argument got the flag '{param}' instead.");
-                            }
-                            Some(param)
+                    config.output = match args.next() {
+                        Some(param) if param.starts_with('-') => {
+                            return Err(ConfigError::UnexpectedFlagAfterOutput(param));
                         },
-                        None => {
-                            return Err("Expected an output file name after '-o' flag.");
-                        }
+                        Some(param) => OutputTarget::File(PathBuf::from(param)),
+                        None => return Err(ConfigError::MissingOutputName),
                     };
                 },
-                input_file => config.input_file = input_file.to_string()
+                "--write-chunk" => {
+                    config.write_chunk_size = match args.next().map(|size| size.parse()) {
+                        Some(Ok(size)) if size > 0 => size,
+                        Some(_) => return Err("Expected a positive number of bytes after '--write-chunk' flag.".into()),
+                        None => return Err("Expected a chunk size after '--write-chunk' flag.".into()),
+                    };
+                },
+                "--max-header-bytes" => {
+                    config.max_header_bytes = match args.next().map(|size| size.parse()) {
+                        Some(Ok(size)) => Some(size),
+                        Some(_) => return Err("Expected a number of bytes after '--max-header-bytes' flag.".into()),
+                        None => return Err("Expected a header size after '--max-header-bytes' flag.".into()),
+                    };
+                },
+                "--block-size" => {
+                    config.block_size = match args.next().map(|size| size.parse()) {
+                        Some(Ok(size)) if size > 0 => Some(size),
+                        Some(_) => return Err("Expected a positive number of bytes after '--block-size' flag.".into()),
+                        None => return Err("Expected a block size after '--block-size' flag.".into()),
+                    };
+                },
+                "--time-limit" => {
+                    let secs = args.next()
+                        .ok_or("Expected a number of seconds after '--time-limit' flag.")?;
+
+                    config.time_limit = match secs.parse().map(Duration::try_from_secs_f64) {
+                        Ok(Ok(limit)) => Some(limit),
+                        _ => return Err("Expected a non-negative number of seconds after '--time-limit' flag.".into()),
+                    };
+                },
+                "--transform" => {
+                    let name = args.next().ok_or("Expected a transform name after '--transform' flag.")?;
+
+                    let transform = transform::by_name(&name)
+                        .ok_or("Unknown transform name after '--transform' flag.")?;
+
+                    config.transforms.push(transform.id());
+                },
+                "--verify-against" => {
+                    config.verify_against = Some(
+                        args.next().ok_or("Expected the original file after '--verify-against' flag.")?
+                    );
+                    config.mode = Mode::VerifyAgainst;
+                },
+                "--mode" => {
+                    config.mode = args.next()
+                        .ok_or("Expected `compress`, `decompress`, `inspect` or `verify` after '--mode' flag.")?
+                        .parse()?;
+                },
+                "--algorithm" => {
+                    config.algorithm = args.next()
+                        .ok_or("Expected `huffman` or `shannon-fano` after '--algorithm' flag.")?
+                        .parse()?;
+                },
+                "--unmapped" => {
+                    config.unmapped = args.next()
+                        .ok_or("Expected `error`, `skip` or `substitute=<symbol>` after '--unmapped' flag.")?
+                        .parse()?;
+                },
+                "--weights" => {
+                    config.weights = Some(
+                        args.next().ok_or("Expected a weights file after '--weights' flag.")?
+                    );
+                },
+                "--dictionary" => {
+                    config.dictionary = Some(
+                        args.next().ok_or("Expected a model file after '--dictionary' flag.")?
+                    );
+                },
+                // the model of the sample is the dictionary
+                "--train" => {
+                    config.export_model = true;
+                    config.inputs.push(args.next().ok_or("Expected a sample file after '--train' flag.")?);
+                },
+                "--model" => {
+                    config.model = Some(
+                        args.next().ok_or("Expected a model file after '--model' flag.")?
+                    );
+                },
+                "--encoding" => {
+                    config.encoding = Some(
+                        args.next().ok_or("Expected an encoding label after '--encoding' flag.")?
+                    );
+                },
+                // everything after `--` is an input file, even when it starts with `-`
+                "--" => config.inputs.extend(args.by_ref()),
+                // a mistyped flag isn't taken for a file name
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(ConfigError::Invalid("Unknown flag, use '--' before an input file name starting with '-'."));
+                },
+                input_file => config.inputs.push(input_file.to_string())
             }
         }
 
-        Ok(config)
+        // a single input is just `input_file`, several are archived together
+        if let Some(first) = config.inputs.first() {
+            config.input_file = first.clone();
+        }
+
+        if config.inputs.len() < 2 {
+            config.inputs.clear();
+        }
+
+        ConfigBuilder { config }.build()
     }
 
+    /// Path of the output file, nothing is created until the output is written.
+    /// Without `-o` decompression strips the `.o` compression added, or adds
+    /// `.decoded` to an input without it.
+    /// Fails for `OutputTarget::Stdout`, which has no file, and for an
+    /// existing file unless `force` or `auto_rename` is set.
     pub fn get_output_file(&self) -> Result<PathBuf, IoError> {
-        let out_filename = match self.output_file.clone() {
-            Some(name) => name,
-            None => self.input_file.clone() + ".o"
+        let mut path_buf = match &self.output {
+            OutputTarget::File(path) => path.clone(),
+            OutputTarget::Derived if self.self_extract => PathBuf::from(self.input_file.clone() + ".sh"),
+            OutputTarget::Derived if self.mode == Mode::Decompress => match self.input_file.strip_suffix(".o") {
+                // `.o` alone names no file
+                Some(original) if !original.is_empty() && !original.ends_with(std::path::is_separator) => PathBuf::from(original),
+                _ => PathBuf::from(self.input_file.clone() + ".decoded"),
+            },
+            OutputTarget::Derived => PathBuf::from(self.input_file.clone() + ".o"),
+            OutputTarget::Stdout => {
+                return Err(IoError::new(ErrorKind::InvalidInput, "the output goes to stdout, not a file"));
+            },
         };
 
-        let path_buf = PathBuf::from(out_filename);
-
-        if !path_buf.exists() {
-            File::create(&path_buf)?;
+        if self.auto_rename {
+            path_buf = next_available_path(&path_buf);
+        } else if !self.force && path_buf.exists() {
+            return Err(IoError::new(
+                ErrorKind::AlreadyExists,
+                format!("output file `{}` already exists, use '--force' to overwrite it", path_buf.display())
+            ));
         }
 
         Ok(path_buf)
     }
 
+    /// Writes the finished output, either to stdout or, through a temporary
+    /// file and a rename, to the output file (the input file in place) so
+    /// a failed write leaves neither a partial output nor a half written input.
+    /// Returns the path written to, `None` for stdout.
+    pub fn write_output(&self, contents: &[u8]) -> Result<Option<PathBuf>, IoError> {
+        if self.output == OutputTarget::Stdout {
+            decode::write_decoded(&mut std::io::stdout().lock(), contents, self.write_chunk_size)?;
+
+            return Ok(None);
+        }
+
+        let target = self.output_path()?;
+
+        TempOutput::write(temp_path(&target), contents, self.write_chunk_size)
+            .map_err(|err| describe_output_error(&target, err))?
+            .rename_to(&target)?;
+
+        Ok(Some(target))
+    }
+
+    /// Lets `write` produce the output while it's being encoded, like `write_output`
+    /// through a temporary file that is removed again if `write` fails.
+    pub fn write_output_with<T, E: From<IoError>>(
+        &self,
+        write: impl FnOnce(&mut dyn Write) -> Result<T, E>,
+    ) -> Result<T, E> {
+        if self.output == OutputTarget::Stdout {
+            let mut out = std::io::BufWriter::with_capacity(self.write_chunk_size, std::io::stdout().lock());
+            let written = write(&mut out)?;
+            out.flush()?;
+
+            return Ok(written);
+        }
+
+        let target = self.output_path()?;
+
+        let (temp, file) = TempOutput::create(temp_path(&target))
+            .map_err(|err| describe_output_error(&target, err))?;
+
+        let mut out = std::io::BufWriter::with_capacity(self.write_chunk_size, file);
+
+        let written = write(&mut out).and_then(|written| {
+            out.flush()?;
+            Ok(written)
+        });
+
+        drop(out);
+
+        match written {
+            Ok(written) => {
+                temp.rename_to(&target)?;
+                Ok(written)
+            },
+            Err(err) => {
+                temp.discard();
+                Err(err)
+            },
+        }
+    }
+
+    /// The file the output goes to: the input file in place, otherwise the output file.
+    fn output_path(&self) -> Result<PathBuf, IoError> {
+        match self.in_place {
+            true => Ok(self.get_input_file()),
+            false => self.get_output_file(),
+        }
+    }
+
     #[inline(always)]
     pub fn get_input_file(&self) -> PathBuf {
         PathBuf::from(&self.input_file)
     }
+
+    /// The input file, or stdin when it's `-` or not given.
+    pub fn input_source(&self) -> InputSource {
+        match self.input_file.as_str() {
+            "" | "-" => InputSource::Stdin,
+            path => InputSource::File(PathBuf::from(path)),
+        }
+    }
+}
+
+/// A completely written temporary file, removed again if writing or renaming it fails.
+pub(crate) struct TempOutput {
+    path: PathBuf,
+}
+
+impl TempOutput {
+    /// Creates the file empty, for writing to it while it's being produced.
+    pub(crate) fn create(path: PathBuf) -> Result<(TempOutput, File), IoError> {
+        let file = File::create(&path)?;
+
+        Ok((TempOutput { path }, file))
+    }
+
+    pub(crate) fn write(path: PathBuf, contents: &[u8], chunk_size: usize) -> Result<TempOutput, IoError> {
+        let file = File::create(&path)?;
+
+        TempOutput { path }.fill(file, contents, chunk_size)
+    }
+
+    /// Like `write`, but fails with `AlreadyExists` instead of opening whatever
+    /// is at `path` already, a symlink included.
+    pub(crate) fn write_new(path: PathBuf, contents: &[u8], chunk_size: usize) -> Result<TempOutput, IoError> {
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+
+        TempOutput { path }.fill(file, contents, chunk_size)
+    }
+
+    fn fill(self, mut file: File, contents: &[u8], chunk_size: usize) -> Result<TempOutput, IoError> {
+        match encode::write_chunked(&mut file, contents, chunk_size) {
+            Ok(()) => Ok(self),
+            Err(err) => {
+                self.discard();
+                Err(err)
+            },
+        }
+    }
+
+    /// Moves the file over `target`.
+    pub(crate) fn rename_to(self, target: &Path) -> Result<(), IoError> {
+        let renamed = fs::rename(&self.path, target);
+
+        if renamed.is_err() {
+            let _ = fs::remove_file(&self.path);
+        }
+
+        renamed
+    }
+
+    /// Removes the file.
+    pub(crate) fn discard(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+
+    /// Keeps the file where it is, leaving its cleanup to the caller.
+    pub(crate) fn into_path(self) -> PathBuf {
+        self.path
+    }
+}
+
+/// Hidden file next to `target` that the output is written to before replacing it.
+fn temp_path(target: &Path) -> PathBuf {
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(target.file_name().unwrap_or_default());
+    temp_name.push(".huffman-tmp");
+
+    target.with_file_name(temp_name)
+}
+
+/// Returns `path` if nothing exists there yet, otherwise the first free
+/// numbered variant of it: `out.o`, `out.1.o`, `out.2.o`, ...
+fn next_available_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut candidate = path.to_path_buf();
+    let mut n = 1;
+
+    while candidate.exists() {
+        let name = match path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, n, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, n),
+        };
+
+        candidate = path.with_file_name(name);
+        n += 1;
+    }
+
+    candidate
+}
+
+fn describe_output_error(path: &Path, err: IoError) -> IoError {
+    let message = match err.kind() {
+        ErrorKind::PermissionDenied => format!(
+            "cannot write output file `{}`: permission denied, check the permissions of the file and its directory",
+            path.display()
+        ),
+        ErrorKind::NotFound => format!(
+            "cannot create output file `{}`: the parent directory does not exist",
+            path.display()
+        ),
+        _ => format!("cannot open output file `{}`: {}", path.display(), err),
+    };
+
+    IoError::new(err.kind(), message)
 }
 
 pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let detected;
+
+    let config = match config.auto {
+        true => {
+            detected = Config { mode: detect_mode(config)?, ..config.clone() };
+            &detected
+        },
+        false => config,
+    };
+
+    if config.pipe {
+        return pipe(config, &mut std::io::stdin().lock(), &mut std::io::stdout().lock());
+    }
+
     match config.mode {
-        Mode::Compress => encode::compress(&config)?,
-        Mode::Decompress => decode::decompress(&config)?,
+        Mode::Compress => {
+            let stats = encode::compress_with_stats(config)?;
+
+            // stderr, the output may be going to stdout
+            if !config.quiet {
+                eprintln!("{}", stats);
+            }
+        },
+        Mode::Decompress => decode::decompress(config)?,
+        Mode::Inspect => println!("{}", decode::inspect(config.get_input_file())?),
+        Mode::SelfTest => selftest::run(&mut std::io::stdout())?,
+        Mode::VerifyAgainst => {
+            // set together with the mode by `from_iter`
+            let original = config.verify_against.as_deref().unwrap_or_default();
+
+            match decode::verify_against(config.get_input_file(), original)? {
+                None => println!("`{}` matches `{}`", config.input_file, original),
+                Some(mismatch) => {
+                    return Err(format!(
+                        "`{}` differs from `{}` at byte {}\n{}",
+                        config.input_file, original, mismatch.offset, mismatch
+                    ).into());
+                },
+            }
+        },
+        Mode::Verify => {
+            let mut input = Vec::new();
+            config.input_source().open()?.read_to_end(&mut input)?;
+
+            match decode::verify_round_trip(config, &input)? {
+                None => println!("`{}` round-trips losslessly ({} bytes)", config.input_file, input.len()),
+                Some(mismatch) => {
+                    return Err(format!(
+                        "the round trip of `{}` differs at byte {}\n{}",
+                        config.input_file, mismatch.offset, mismatch
+                    ).into());
+                },
+            }
+        },
     }
 
     Ok(())
 }
+
+/// The mode `--auto` picks: decompression for an input starting with the magic
+/// bytes of a compressed file or archive, compression otherwise. Only the buffer
+/// of the input is looked at, stdin keeps its bytes for the real read.
+fn detect_mode(config: &Config) -> Result<Mode, IoError> {
+    let compressed = match config.input_source() {
+        InputSource::File(path) => decode::starts_compressed(&mut std::io::BufReader::new(File::open(path)?))?,
+        InputSource::Stdin => decode::starts_compressed(&mut std::io::stdin().lock())?,
+    };
+
+    Ok(match compressed {
+        true => Mode::Decompress,
+        false => Mode::Compress,
+    })
+}
+
+/// Compresses or decompresses `input` into `output` as a filter, used by `--pipe`.
+/// The output is only written once the whole input has been processed,
+/// a closed stdout (`BrokenPipe`) stops writing without an error.
+pub fn pipe<R: BufRead, W: Write>(config: &Config, input: &mut R, output: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut processed = Vec::new();
+
+    match config.mode {
+        Mode::Compress => {
+            let mut contents = Vec::new();
+            input.read_to_end(&mut contents)?;
+
+            encode::compress_with(config, &contents, &mut processed)?;
+        },
+        Mode::Decompress => decode::decompress_with(config, input, &mut processed)?,
+        _ => return Err(format!("{} can't be combined with '--pipe'", config.mode).into()),
+    }
+
+    Ok(decode::write_decoded(output, &processed, config.write_chunk_size)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        Config::from_iter(std::iter::once("huffman").chain(args.iter().copied()).map(String::from))
+    }
+
+    #[test]
+    fn pipe_round_trip() {
+        let contents = "filtered from stdin to stdout and back\n".repeat(40);
+
+        let mut compressed = Vec::new();
+        pipe(&parse(&["--pipe"]).unwrap(), &mut contents.as_bytes(), &mut compressed).unwrap();
+
+        assert!(compressed.starts_with(MAGIC));
+
+        let mut decompressed = Vec::new();
+        pipe(&parse(&["--pipe", "-d"]).unwrap(), &mut &compressed[..], &mut decompressed).unwrap();
+
+        assert_eq!(decompressed, contents.as_bytes());
+
+        assert!(parse(&["--pipe", "bird"]).is_err());
+        assert!(parse(&["--pipe", "-o", "bird.o"]).is_err());
+        assert!(parse(&["--pipe", "--in-place"]).is_err());
+    }
+
+    /// Non-seekable reader handing out one byte per read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(first)) => {
+                    *first = byte;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn stdin_input() {
+        let config = parse(&["-"]).unwrap();
+        assert_eq!(config.input_source(), InputSource::Stdin);
+        assert_eq!(config.output, OutputTarget::Stdout);
+
+        assert_eq!(parse(&["-d"]).unwrap().input_source(), InputSource::Stdin);
+        assert_eq!(parse(&["-", "-o", "out.o"]).unwrap().output, OutputTarget::File(PathBuf::from("out.o")));
+        assert_eq!(parse(&["bird"]).unwrap().input_source(), InputSource::File(PathBuf::from("bird")));
+
+        assert!(parse(&["-", "--stream"]).is_err());
+        assert!(parse(&["--in-place"]).is_err());
+    }
+
+    #[test]
+    fn round_trip_through_readers() {
+        let compressed = std::env::temp_dir().join("huffman_from_reader.o");
+        let restored = std::env::temp_dir().join("huffman_from_reader.txt");
+
+        let contents = "read from a stream that can't seek back\n".repeat(40);
+
+        let config = parse(&["-", "-o", &compressed.to_string_lossy()]).unwrap();
+        encode::compress_from(&config, Trickle(contents.as_bytes())).unwrap();
+
+        let archive = fs::read(&compressed).unwrap();
+        assert!(archive.starts_with(MAGIC));
+
+        let config = parse(&["-d", "-o", &restored.to_string_lossy()]).unwrap();
+        decode::decompress_from(&config, std::io::BufReader::new(Trickle(&archive))).unwrap();
+
+        assert_eq!(fs::read(&restored).unwrap(), contents.as_bytes());
+
+        fs::remove_file(&compressed).unwrap();
+        fs::remove_file(&restored).unwrap();
+    }
+
+    #[test]
+    fn existing_output_needs_force() {
+        let input = std::env::temp_dir().join("huffman_force_input.txt");
+        let output = std::env::temp_dir().join("huffman_force_input.txt.o");
+
+        let contents = "overwritten only when forced\n".repeat(40);
+        fs::write(&input, &contents).unwrap();
+        fs::write(&output, b"a real file").unwrap();
+
+        let input_file = input.to_string_lossy();
+
+        let err = encode::compress(&parse(&[&input_file]).unwrap()).unwrap_err();
+        let err = err.downcast::<IoError>().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&output).unwrap(), b"a real file");
+
+        encode::compress(&parse(&[&input_file, "--force"]).unwrap()).unwrap();
+
+        assert!(fs::read(&output).unwrap().starts_with(MAGIC));
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn auto_picks_the_mode_from_the_magic() {
+        let dir = std::env::temp_dir().join("huffman_auto");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let contents = "no -d needed to undo this\n".repeat(40);
+        fs::write(dir.join("input"), &contents).unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        run(&parse(&["--auto", "--quiet", &path("input"), "-o", &path("compressed")]).unwrap()).unwrap();
+        assert!(fs::read(dir.join("compressed")).unwrap().starts_with(MAGIC));
+
+        run(&parse(&["--auto", &path("compressed"), "-o", &path("restored")]).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("restored")).unwrap(), contents);
+
+        // input that merely starts like the magic is compressed
+        fs::write(dir.join("almost"), b"HU").unwrap();
+        assert_eq!(detect_mode(&parse(&["--auto", &path("almost")]).unwrap()).unwrap(), Mode::Compress);
+
+        assert!(matches!(parse(&["--auto", "-d", "bird"]), Err(ConfigError::Invalid(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_leaves_no_output() {
+        let output = std::env::temp_dir().join("huffman_failed_write.o");
+        let _ = fs::remove_file(&output);
+
+        let config = parse(&["bird", "-o", &output.to_string_lossy()]).unwrap();
+
+        let result: Result<(), IoError> = config.write_output_with(|out| {
+            out.write_all(&[0; 64 * 1024])?;
+            Err(IoError::other("failed halfway"))
+        });
+
+        assert!(result.is_err());
+        assert!(!output.exists());
+        assert!(!temp_path(&output).exists());
+
+        // an output overwritten with `--force` survives a failed write
+        fs::write(&output, b"previous output").unwrap();
+
+        let config = parse(&["bird", "-o", &output.to_string_lossy(), "--force"]).unwrap();
+
+        let result: Result<(), IoError> = config.write_output_with(|_| Err(IoError::other("failed")));
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&output).unwrap(), b"previous output");
+
+        config.write_output(b"new output").unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"new output");
+
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn builder_matches_arguments() {
+        let built = Config::builder()
+            .input_file("bird")
+            .output_file(Some("bird.huff".to_string()))
+            .mode(Mode::Decompress)
+            .build()
+            .unwrap();
+
+        let parsed = parse(&["-d", "bird", "-o", "bird.huff"]).unwrap();
+
+        assert_eq!(built.input_file, parsed.input_file);
+        assert_eq!(built.output, parsed.output);
+        assert!(matches!(built.mode, Mode::Decompress));
+
+        // stdin without an output file goes to stdout, as on the command line
+        assert_eq!(Config::builder().build().unwrap().output, OutputTarget::Stdout);
+        assert_eq!(Config::builder().input_file("bird").output_file(None).build().unwrap().output, OutputTarget::Derived);
+
+        assert_eq!(Config::builder().mode(Mode::Inspect).build().err(), Some(ConfigError::MissingInputFile));
+    }
+
+    #[test]
+    fn input_after_terminator_may_start_with_a_dash() {
+        let config = parse(&["-d", "--", "-weirdname.txt"]).unwrap();
+
+        assert_eq!(config.input_file, "-weirdname.txt");
+        assert!(matches!(config.mode, Mode::Decompress));
+        assert_eq!(config.get_output_file().unwrap(), PathBuf::from("-weirdname.txt.decoded"));
+
+        // flags after `--` are file names too
+        assert_eq!(parse(&["--", "-d"]).unwrap().input_file, "-d");
+        assert_eq!(parse(&["--", "-"]).unwrap().input_source(), InputSource::Stdin);
+    }
+
+    #[test]
+    fn unknown_flags_are_an_error() {
+        for flag in ["--verfiy", "--forse", "-x", "-weirdname.txt"] {
+            assert!(matches!(parse(&["robin", flag]), Err(ConfigError::Invalid(_))), "{}", flag);
+        }
+
+        // `-` alone is stdin, not a flag
+        assert_eq!(parse(&["-"]).unwrap().input_source(), InputSource::Stdin);
+    }
+
+    #[test]
+    fn several_inputs_make_an_archive() {
+        let config = parse(&["robin", "-o", "birds.o", "--", "wren", "-jay"]).unwrap();
+
+        assert_eq!(config.inputs, ["robin", "wren", "-jay"]);
+        assert_eq!(config.input_file, "robin");
+
+        assert!(parse(&["robin"]).unwrap().inputs.is_empty());
+
+        // the archive needs a name, and decompression takes one file
+        assert!(matches!(parse(&["robin", "wren"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(parse(&["-d", "robin", "wren", "--stdout"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(parse(&["--in-place", "robin", "wren", "--stdout"]), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn modes_parse_by_name() {
+        for (names, mode) in [
+            (["compress", "c"], Mode::Compress),
+            (["decompress", "d"], Mode::Decompress),
+            (["inspect", "i"], Mode::Inspect),
+            (["verify", "v"], Mode::Verify),
+        ] {
+            for name in names {
+                assert_eq!(name.parse::<Mode>(), Ok(mode.clone()));
+            }
+        }
+
+        assert!("Decompress".parse::<Mode>().is_err());
+        assert!("".parse::<Mode>().is_err());
+
+        assert_eq!(parse(&["--mode", "decompress", "bird.o"]).unwrap().mode, Mode::Decompress);
+        assert_eq!(parse(&["-d", "--mode", "c", "bird"]).unwrap().mode, Mode::Compress);
+        assert!(matches!(parse(&["--mode", "shrink", "bird"]), Err(ConfigError::Invalid(_))));
+        assert_eq!(parse(&["bird", "--mode"]).err(), Some(ConfigError::Invalid("Expected `compress`, `decompress`, `inspect` or `verify` after '--mode' flag.")));
+    }
+
+    #[test]
+    fn train_exports_the_model_of_the_sample() {
+        let config = parse(&["--train", "sample.txt", "-o", "model.tbl"]).unwrap();
+
+        assert!(config.export_model);
+        assert_eq!(config.input_file, "sample.txt");
+        assert!(config.inputs.is_empty());
+
+        assert!(matches!(parse(&["--train", "sample.txt", "--dictionary", "model.tbl"]), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn decompression_strips_the_compressed_suffix() {
+        let output = |args: &[&str]| parse(args).unwrap().get_output_file().unwrap();
+
+        assert_eq!(output(&["-d", "file.txt.o"]), PathBuf::from("file.txt"));
+        assert_eq!(output(&["-d", "dir/file.o"]), PathBuf::from("dir/file"));
+
+        // nothing to strip
+        assert_eq!(output(&["-d", "file.huff"]), PathBuf::from("file.huff.decoded"));
+        assert_eq!(output(&["-d", "dir/.o"]), PathBuf::from("dir/.o.decoded"));
+
+        // compression and `-o` are unchanged
+        assert_eq!(output(&["file.txt.o"]), PathBuf::from("file.txt.o.o"));
+        assert_eq!(output(&["-d", "file.txt.o", "-o", "out"]), PathBuf::from("out"));
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_eq!(Config::from_iter(std::iter::empty()).err(), Some(ConfigError::MissingProgramName));
+
+        assert_eq!(parse(&["bird", "-o"]).err(), Some(ConfigError::MissingOutputName));
+        assert_eq!(
+            parse(&["bird", "-o", "--best"]).err(),
+            Some(ConfigError::UnexpectedFlagAfterOutput("--best".to_string()))
+        );
+
+        assert!(matches!(parse(&["--in-place", "-o", "bird.o", "bird"]), Err(ConfigError::Invalid(_))));
+
+        // stdin can't stand in for these
+        assert_eq!(parse(&["--inspect"]).err(), Some(ConfigError::MissingInputFile));
+        assert_eq!(parse(&["--stream", "-o", "out"]).err(), Some(ConfigError::MissingInputFile));
+        assert_eq!(parse(&["--verify-against", "bird"]).err(), Some(ConfigError::MissingInputFile));
+        assert!(matches!(parse(&["--algorithm", "morse"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(parse(&["bird", "--block-size", "0"]), Err(ConfigError::Invalid(_))));
+        assert!(matches!(parse(&["bird", "--dictionary", "bird.tbl", "--remap"]), Err(ConfigError::Invalid(_))));
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Write};
+
+/// Dense renumbering of the symbols present in the input.
+///
+/// The `i`th smallest symbol is coded as the char with scalar value `i`,
+/// so the table entries of a sparse alphabet (CJK, emoji, ..) take one byte
+/// per symbol instead of three or four. The mapping is stored as the LEB128
+/// deltas between the sorted symbols, usually a byte each.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Remap {
+    symbols: Vec<char>,
+}
+
+impl Remap {
+    /// Collects the symbols of `text`, `None` when there are too many
+    /// to number them below the surrogate range.
+    pub fn from_text(text: &str) -> Option<Remap> {
+        let mut symbols: Vec<char> = text.chars().collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        (symbols.len() <= 0xd800).then_some(Remap { symbols })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Replaces every symbol of `text` with its index.
+    /// # Panics
+    /// - `text` has a symbol the mapping wasn't built from
+    pub fn apply(&self, text: &str) -> String {
+        let indices: HashMap<char, char> = self.symbols
+            .iter()
+            .enumerate()
+            // below the surrogate range by `from_text`, unwrapping can't fail.
+            .map(|(i, &symbol)| (symbol, char::from_u32(i as u32).unwrap()))
+            .collect();
+
+        text.chars().map(|symbol| indices[&symbol]).collect()
+    }
+
+    /// Replaces every index of `text` with its symbol.
+    pub fn reverse(&self, text: &str) -> Result<String, Error> {
+        text.chars()
+            .map(|index| self.symbols.get(index as usize).copied())
+            .collect::<Option<String>>()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "decoded index is outside the symbol mapping"))
+    }
+
+    /// Writes `<count>:` followed by the LEB128 deltas of the sorted symbols.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        out.write_all(format!("{}:", self.symbols.len()).as_bytes())?;
+
+        let mut previous = 0;
+
+        for &symbol in &self.symbols {
+            let mut delta = symbol as u32 - previous;
+            previous = symbol as u32;
+
+            loop {
+                let byte = (delta & 0x7f) as u8;
+                delta >>= 7;
+
+                if delta == 0 {
+                    out.write_all(&[byte])?;
+                    break;
+                }
+
+                out.write_all(&[byte | 0x80])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the deltas written by `write_to`, `count` being the already read prefix.
+    pub fn read_from<R: Read>(reader: &mut R, count: usize) -> Result<Remap, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "invalid symbol mapping in header");
+
+        let mut symbols = Vec::with_capacity(count.min(0xd800));
+        let mut previous: u32 = 0;
+
+        for _ in 0..count {
+            let mut delta: u32 = 0;
+            let mut shift = 0;
+
+            loop {
+                let mut byte = [0u8];
+                reader.read_exact(&mut byte)?;
+
+                let bits = (byte[0] & 0x7f) as u32;
+                delta |= bits.checked_shl(shift).filter(|&shifted| shifted >> shift == bits).ok_or_else(invalid)?;
+                shift += 7;
+
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+
+            previous = previous.checked_add(delta).ok_or_else(invalid)?;
+            symbols.push(char::from_u32(previous).ok_or_else(invalid)?);
+        }
+
+        Ok(Remap { symbols })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mapping_round_trip() {
+        let text = "稀疏的字母表 🦀 sparse";
+
+        let remap = Remap::from_text(text).unwrap();
+        let remapped = remap.apply(text);
+
+        assert!(remapped.chars().all(|index| (index as usize) < remap.symbols.len()));
+        assert_eq!(remap.reverse(&remapped).unwrap(), text);
+
+        let mut header = Vec::new();
+        remap.write_to(&mut header).unwrap();
+
+        let count_len = format!("{}:", remap.symbols.len()).len();
+        let read = Remap::read_from(&mut &header[count_len..], remap.symbols.len()).unwrap();
+
+        assert_eq!(read, remap);
+    }
+
+    #[test]
+    fn index_outside_mapping_is_an_error() {
+        let remap = Remap::from_text("ab").unwrap();
+
+        assert_eq!(remap.reverse("\u{2}").unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,175 @@
+//! Internal invariant checks run by `--self-test`, a health check
+//! of the build that doesn't need the test suite.
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Write};
+use crate::decode::{self, Root};
+use crate::encode;
+use crate::footer::Footer;
+use crate::{transform, Config};
+
+/// Inputs every check runs on.
+const SAMPLES: &[&str] = &[
+    "",
+    "the quick brown fox jumps over the lazy dog\n",
+    "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbcccccccddd\n\n\n\n",
+    "ünïcödé, 稀疏的字母表 and 🦀 crabs\r\n",
+];
+
+/// Runs every check on every sample, writing a pass/fail line per check to `out`.
+/// Errors when any check fails.
+pub fn run<W: Write>(out: &mut W) -> io::Result<()> {
+    let mut failed = 0;
+
+    for (i, sample) in SAMPLES.iter().enumerate() {
+        // repeated so that the samples go through the huffman path too
+        for input in [sample.to_string(), sample.repeat(40)] {
+            let checks: [(&str, Result<(), String>); 4] = [
+                ("kraft sum", check_kraft_sum(&input)),
+                ("leaf count", check_leaf_count(&input)),
+                ("round trip", check_round_trip(&input)),
+                ("checksum", check_checksum(&input)),
+            ];
+
+            for (name, result) in checks {
+                match result {
+                    Ok(()) => writeln!(out, "pass: {} (sample {}, {} bytes)", name, i, input.len())?,
+                    Err(reason) => {
+                        failed += 1;
+                        writeln!(out, "FAIL: {} (sample {}, {} bytes): {}", name, i, input.len(), reason)?;
+                    },
+                }
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(io::Error::other(format!("{} self-test checks failed", failed))),
+    }
+}
+
+/// The codes of a complete prefix code fill the code space: `sum(2^-len) == 1`.
+fn check_kraft_sum(input: &str) -> Result<(), String> {
+    // no tree without symbols, and a lone symbol gets a single code
+    if alphabet(input).len() < 2 {
+        return Ok(());
+    }
+
+    let table = encode::generate_encoding_table(input).map_err(|err| err.to_string())?;
+
+    let sum: f64 = table.values().map(|code| 0.5f64.powi(code.len() as i32)).sum();
+
+    match sum == 1.0 {
+        true => Ok(()),
+        false => Err(format!("codes sum to {}", sum)),
+    }
+}
+
+/// Rebuilding the tree from the table keeps one leaf per distinct symbol.
+fn check_leaf_count(input: &str) -> Result<(), String> {
+    let alphabet = alphabet(input);
+
+    if alphabet.len() < 2 {
+        return Ok(());
+    }
+
+    let table = encode::generate_encoding_table(input).map_err(|err| err.to_string())?;
+
+    let raw_table: HashMap<char, String> = table
+        .iter()
+        .map(|(&symbol, code)| (symbol, code.iter().by_vals().map(|bit| if bit { '1' } else { '0' }).collect()))
+        .collect();
+
+    let leaves = Root::from_table(&raw_table).leaf_count();
+
+    match leaves == alphabet.len() {
+        true => Ok(()),
+        false => Err(format!("{} leaves for {} symbols", leaves, alphabet.len())),
+    }
+}
+
+/// Compressing then decompressing gives back the input, with and without the optional steps.
+fn check_round_trip(input: &str) -> Result<(), String> {
+    let configs = [
+        Config::default(),
+        Config { remap: true, ..Config::default() },
+        Config { algorithm: encode::Algorithm::ShannonFano, ..Config::default() },
+        Config {
+            transforms: transform::REGISTRY.iter().map(|transform| transform.id()).collect(),
+            ..Config::default()
+        },
+    ];
+
+    for config in configs {
+        let decoded = round_trip(&config, input)?;
+
+        if decoded != input.as_bytes() {
+            return Err("decoded output differs from the input".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// The footer checksum is the CRC-32 of the input and matches the decoded output.
+fn check_checksum(input: &str) -> Result<(), String> {
+    let mut compressed = Vec::new();
+    encode::compress_to_writer(input.as_bytes(), &mut compressed).map_err(|err| err.to_string())?;
+
+    let footer = Footer::read_from(&mut Cursor::new(&compressed)).map_err(|err| err.to_string())?;
+
+    let decoded = round_trip(&Config::default(), input)?;
+
+    if decoded.len() as u64 != footer.original_len {
+        return Err(format!("decoded {} bytes but the footer declares {}", decoded.len(), footer.original_len));
+    }
+
+    match crc32fast::hash(&decoded) == footer.checksum && footer.checksum == crc32fast::hash(input.as_bytes()) {
+        true => Ok(()),
+        false => Err(format!("footer checksum {:08x} doesn't match", footer.checksum)),
+    }
+}
+
+fn alphabet(input: &str) -> Vec<char> {
+    let mut alphabet: Vec<char> = input.chars().collect();
+    alphabet.sort_unstable();
+    alphabet.dedup();
+
+    alphabet
+}
+
+fn round_trip(config: &Config, input: &str) -> Result<Vec<u8>, String> {
+    let mut compressed = Vec::new();
+    encode::compress_with(config, input.as_bytes(), &mut compressed).map_err(|err| err.to_string())?;
+
+    let mut decoded = Vec::new();
+    decode::decompress_from_reader(&mut &compressed[..], &mut decoded).map_err(|err| err.to_string())?;
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn self_test_passes() {
+        let mut report = Vec::new();
+
+        run(&mut report).unwrap();
+
+        let report = String::from_utf8(report).unwrap();
+
+        assert!(!report.contains("FAIL"));
+        assert_eq!(report.lines().count(), SAMPLES.len() * 2 * 4);
+    }
+
+    #[test]
+    fn self_test_flag_succeeds() {
+        let config = Config::from_iter(["huffman", "--self-test"].map(String::from).into_iter()).unwrap();
+
+        assert!(matches!(config.mode, crate::Mode::SelfTest));
+        assert!(crate::run(&config).is_ok());
+    }
+}
@@ -0,0 +1,155 @@
+//! Reversible transforms applied to the text before huffman coding.
+//!
+//! The ids of the applied transforms are stored in the header so decompression
+//! can reverse them, last applied first. Transforms see the UTF-8 bytes of the
+//! text and must produce valid UTF-8 for the char based huffman model.
+
+/// A reversible pre-processing step around the huffman coder.
+pub trait Transform {
+    /// Identifier stored in the header, unique within `REGISTRY`.
+    fn id(&self) -> u8;
+
+    /// Name used to select the transform on the command line.
+    fn name(&self) -> &'static str;
+
+    fn encode(&self, data: &[u8]) -> Vec<u8>;
+
+    fn decode(&self, data: &[u8]) -> Vec<u8>;
+}
+
+/// Every transform known to this build.
+pub static REGISTRY: &[&(dyn Transform + Sync)] = &[&Rle];
+
+pub fn by_id(id: u8) -> Option<&'static (dyn Transform + Sync)> {
+    REGISTRY.iter().copied().find(|transform| transform.id() == id)
+}
+
+pub fn by_name(name: &str) -> Option<&'static (dyn Transform + Sync)> {
+    REGISTRY.iter().copied().find(|transform| transform.name() == name)
+}
+
+/// Number of repeats after which a count follows.
+const RLE_MIN_RUN: usize = 3;
+
+/// Largest count, kept below the surrogate range so every count is a valid `char`.
+const RLE_MAX_EXTRA: usize = 0xd7ff;
+
+/// Run length encoding over chars.
+///
+/// After `RLE_MIN_RUN` equal chars the next char is not data but a count
+/// (its scalar value) of how many more times the char repeats, so runs of
+/// three or more shrink to four chars.
+pub struct Rle;
+
+impl Transform for Rle {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn name(&self) -> &'static str {
+        "rle"
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(data);
+        let chars: Vec<char> = text.chars().collect();
+
+        let mut encoded = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let symbol = chars[i];
+
+            let run = chars[i..]
+                .iter()
+                .take(RLE_MIN_RUN + RLE_MAX_EXTRA)
+                .take_while(|&&next| next == symbol)
+                .count();
+
+            encoded.extend(std::iter::repeat_n(symbol, run.min(RLE_MIN_RUN)));
+
+            if run >= RLE_MIN_RUN {
+                // in range by `RLE_MAX_EXTRA`, unwrapping can't fail.
+                encoded.push(char::from_u32((run - RLE_MIN_RUN) as u32).unwrap());
+            }
+
+            i += run;
+        }
+
+        encoded.into_bytes()
+    }
+
+    fn decode(&self, data: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(data);
+
+        let mut decoded = String::new();
+        let mut last: Option<char> = None;
+        let mut run = 0;
+
+        for symbol in text.chars() {
+            if run == RLE_MIN_RUN {
+                if let Some(repeated) = last {
+                    decoded.extend(std::iter::repeat_n(repeated, symbol as usize));
+                }
+
+                // a run starts over after its count.
+                last = None;
+                run = 0;
+                continue;
+            }
+
+            if last == Some(symbol) {
+                run += 1;
+            } else {
+                last = Some(symbol);
+                run = 1;
+            }
+
+            decoded.push(symbol);
+        }
+
+        decoded.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rle_round_trip() {
+        let inputs = [
+            "",
+            "abc",
+            "aab",
+            "aaab",
+            "aaaaaaaaaaaaab\n\n\n\n\nb",
+            "ééééé語語語語",
+        ];
+
+        for input in inputs {
+            let encoded = Rle.encode(input.as_bytes());
+
+            assert!(String::from_utf8(encoded.clone()).is_ok());
+            assert_eq!(Rle.decode(&encoded), input.as_bytes(), "input: {:?}", input);
+        }
+
+        let long_run = "x".repeat(RLE_MIN_RUN + RLE_MAX_EXTRA + 10);
+
+        assert_eq!(Rle.decode(&Rle.encode(long_run.as_bytes())), long_run.as_bytes());
+    }
+
+    #[test]
+    fn rle_shrinks_runs() {
+        let encoded = Rle.encode("a".repeat(100).as_bytes());
+
+        assert_eq!(encoded, "aaa\u{61}".as_bytes());
+    }
+
+    #[test]
+    fn registry_lookup() {
+        assert_eq!(by_name("rle").map(|transform| transform.id()), Some(1));
+        assert_eq!(by_id(1).map(|transform| transform.name()), Some("rle"));
+        assert!(by_id(0).is_none());
+    }
+}
@@ -0,0 +1,78 @@
+//! Golden file tests locking down the compressed format.
+//!
+//! Every `tests/fixtures/<name>` input is compressed and compared byte for byte
+//! with `tests/fixtures/<name>.huff`. When the format changes on purpose
+//! (bump `FORMAT_VERSION`), regenerate the fixtures with
+//!
+//! ```text
+//! HUFFMAN_BLESS=1 cargo test --test golden
+//! ```
+//!
+//! and commit the updated `.huff` files together with the change.
+
+use std::fs;
+use std::path::Path;
+use huffman::Config;
+
+const FIXTURES: &[&str] = &["ascii.txt", "utf8.txt"];
+
+fn compress_fixture(name: &str) -> Vec<u8> {
+    let input = fs::read(Path::new("tests/fixtures").join(name)).unwrap();
+
+    // only the file name ends up in the footer
+    let config = Config {
+        input_file: name.to_string(),
+        ..Config::default()
+    };
+
+    let mut compressed = Vec::new();
+    huffman::encode::compress_with(&config, &input, &mut compressed).unwrap();
+
+    compressed
+}
+
+#[test]
+fn output_matches_golden_files() {
+    let bless = std::env::var_os("HUFFMAN_BLESS").is_some();
+
+    for name in FIXTURES {
+        let golden_path = Path::new("tests/fixtures").join(format!("{}.huff", name));
+        let compressed = compress_fixture(name);
+
+        if bless {
+            fs::write(&golden_path, &compressed).unwrap();
+            continue;
+        }
+
+        let golden = fs::read(&golden_path).unwrap_or_else(|err| {
+            panic!("missing {}, see the module docs to regenerate: {}", golden_path.display(), err)
+        });
+
+        assert!(
+            compressed == golden,
+            "{} drifted from {}, regenerate it if the format changed on purpose",
+            name,
+            golden_path.display()
+        );
+    }
+}
+
+#[test]
+fn golden_files_decompress() {
+    for name in FIXTURES {
+        let input = fs::read(Path::new("tests/fixtures").join(name)).unwrap();
+        let golden = fs::read(Path::new("tests/fixtures").join(format!("{}.huff", name))).unwrap();
+
+        let mut decoded = Vec::new();
+        huffman::decode::decompress_from_reader(&mut &golden[..], &mut decoded).unwrap();
+
+        assert_eq!(decoded, input, "{}", name);
+    }
+}
+
+#[test]
+fn output_is_the_same_every_run() {
+    for name in FIXTURES {
+        assert!(compress_fixture(name) == compress_fixture(name), "{}", name);
+    }
+}
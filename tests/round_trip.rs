@@ -0,0 +1,106 @@
+//! Randomized `encode → decode` round trips through the public API.
+//!
+//! The generator is a fixed seed LCG, so a failing case fails the same way every
+//! run and is reported with its index and the first byte that differs.
+
+use huffman::decode::decode_bytes;
+use huffman::encode::{compress_with, encode_bytes};
+use huffman::Config;
+
+const CASES: usize = 300;
+
+/// Linear congruential generator, good enough to vary the inputs.
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> usize {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (self.0 >> 16) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.next() % items.len()]
+    }
+}
+
+/// Symbols the text inputs are drawn from: control chars, the characters
+/// the header uses as separators and multi-byte UTF-8.
+const SYMBOLS: &[char] = &[
+    '\0', '\x01', '\t', '\n', '\r', '\x1b', '\x7f', ' ', ':', '0', '1', '9', '\\',
+    'a', 'b', 'z', 'é', 'ß', '語', '日', '🦀', '\u{feff}',
+];
+
+fn random_input(rng: &mut Lcg, case: usize) -> Vec<u8> {
+    let len = rng.next() % 600;
+
+    match case % 6 {
+        0 => Vec::new(),
+        // a single symbol, repeated
+        1 => rng.pick(SYMBOLS).to_string().repeat(1 + len).into_bytes(),
+        // long runs of a few symbols
+        2 => (0..len / 8 + 1)
+            .map(|_| rng.pick(&SYMBOLS[..4]).to_string().repeat(1 + rng.next() % 16))
+            .collect::<String>()
+            .into_bytes(),
+        // any bytes, usually not UTF-8
+        3 => (0..len).map(|_| rng.next() as u8).collect(),
+        // two bytes repeated, byte coded
+        4 => (0..len).map(|_| rng.pick(&[0x00, 0xff])).collect(),
+        _ => (0..len).map(|_| rng.pick(SYMBOLS)).collect::<String>().into_bytes(),
+    }
+}
+
+fn assert_round_trip(case: usize, input: &[u8], decoded: &[u8]) {
+    if let Some(index) = input.iter().zip(decoded).position(|(a, b)| a != b) {
+        panic!(
+            "case {}: first mismatch at byte {}, expected {:#04x} found {:#04x}",
+            case, index, input[index], decoded[index]
+        );
+    }
+
+    assert_eq!(decoded.len(), input.len(), "case {}: decoded length differs", case);
+}
+
+#[test]
+fn random_inputs_round_trip() {
+    let mut rng = Lcg(0x0dd_ba11);
+
+    for case in 0..CASES {
+        let input = random_input(&mut rng, case);
+
+        let decoded = decode_bytes(&encode_bytes(&input))
+            .unwrap_or_else(|err| panic!("case {}: {}", case, err));
+
+        assert_round_trip(case, &input, &decoded);
+    }
+}
+
+#[test]
+fn random_text_round_trips_with_every_option() {
+    let mut rng = Lcg(0x5eed);
+
+    let configs = [
+        Config { remap: true, ..Config::default() },
+        Config { transforms: vec![huffman::transform::by_name("rle").unwrap().id()], ..Config::default() },
+        Config { best: true, ..Config::default() },
+        Config { block_size: Some(64), ..Config::default() },
+        Config { algorithm: huffman::encode::Algorithm::ShannonFano, ..Config::default() },
+    ];
+
+    for case in 0..CASES {
+        // text only, the options code symbols of text
+        let input = match random_input(&mut rng, case) {
+            input if std::str::from_utf8(&input).is_ok() => input,
+            _ => continue,
+        };
+
+        let config = &configs[case % configs.len()];
+
+        let mut compressed = Vec::new();
+        compress_with(config, &input, &mut compressed).unwrap_or_else(|err| panic!("case {}: {}", case, err));
+
+        let decoded = decode_bytes(&compressed).unwrap_or_else(|err| panic!("case {}: {}", case, err));
+
+        assert_round_trip(case, &input, &decoded);
+    }
+}
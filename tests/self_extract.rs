@@ -0,0 +1,39 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn self_extracting_script_round_trip() {
+    let dir = std::env::temp_dir().join("huffman_self_extract");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    let input = dir.join("it's bird");
+    let contents = include_str!("../bird").repeat(4);
+    fs::write(&input, &contents).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_huffman"))
+        .arg("--self-extract")
+        .arg(&input)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    fs::remove_file(&input).unwrap();
+
+    let script = dir.join("it's bird.sh");
+
+    let status = Command::new(&script)
+        .current_dir(&dir)
+        .env("HUFFMAN", env!("CARGO_BIN_EXE_huffman"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    assert_eq!(fs::read_to_string(&input).unwrap(), contents);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn stdout_round_trip_leaves_no_files() {
+    let dir = std::env::temp_dir().join("huffman_stdout");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    let input = dir.join("bird");
+    let contents = include_str!("../bird").repeat(4);
+    fs::write(&input, &contents).unwrap();
+
+    let compressed = Command::new(env!("CARGO_BIN_EXE_huffman"))
+        .arg(&input)
+        .arg("--stdout")
+        .output()
+        .unwrap();
+
+    assert!(compressed.status.success());
+    assert!(compressed.stdout.starts_with(huffman::MAGIC));
+
+    let archive = dir.join("bird.huff");
+    fs::write(&archive, &compressed.stdout).unwrap();
+
+    let decompressed = Command::new(env!("CARGO_BIN_EXE_huffman"))
+        .args(["-d", "--stdout"])
+        .arg(&archive)
+        .output()
+        .unwrap();
+
+    assert!(decompressed.status.success());
+
+    assert_eq!(decompressed.stdout, contents.as_bytes());
+
+    // nothing but the input and the archive written above
+    let mut names: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    names.sort();
+    assert_eq!(names, ["bird", "bird.huff"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}